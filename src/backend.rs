@@ -0,0 +1,125 @@
+use document::Document;
+use error::ErrorKind;
+use export::{Options, builtin_backends};
+
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Output format of 'exorg <format> <file>'
+pub trait Backend {
+    /// Format name given on the command line, e.g. 'pdf'
+    fn name(&self) -> &str;
+
+    /// Suffix of the files produced, without dot
+    fn suffix(&self) -> &str;
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind>;
+}
+
+/// Backends available by name
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Registry {
+    /// Registry containing the built-in backends
+    pub fn new() -> Self {
+        let mut registry = Registry::empty();
+        for backend in builtin_backends() {
+            registry.register(backend);
+        }
+        registry
+    }
+
+    pub fn empty() -> Self {
+        Registry { backends: Vec::new() }
+    }
+
+    /// Adds a backend, replacing any registered one of the same name
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        self.backends.retain(|b| !b.name().eq_ignore_ascii_case(backend.name()));
+        self.backends.push(backend);
+    }
+
+    /// Backend of the given name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends.iter()
+                     .find(|b| b.name().eq_ignore_ascii_case(name))
+                     .map(|b| b.as_ref())
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+/// External backend: an executable 'exorg-<format>' found on PATH, like git
+/// finds its subcommands. It is called with the org file and the command line
+/// options as arguments and gets the document as JSON on stdin.
+pub struct Plugin {
+    name: String,
+    path: PathBuf,
+}
+
+impl Plugin {
+    /// Plugin for the given format, if one is installed
+    pub fn find(format: &str) -> Option<Plugin> {
+        let name = format!("exorg-{}", format);
+        let dirs = env::var_os("PATH")?;
+        env::split_paths(&dirs)
+            .map(|dir| dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX)))
+            .find(|path| is_executable(path))
+            .map(|path| Plugin { name: name.clone(), path })
+    }
+}
+
+impl Backend for Plugin {
+    fn name(&self) -> &str {
+        &self.name["exorg-".len()..]
+    }
+
+    // not known in advance
+    fn suffix(&self) -> &str {
+        ""
+    }
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind> {
+        let mut child = Command::new(&self.path)
+            .arg(&document.path)
+            .args(options.to_args())
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|_| ErrorKind::PluginCallFailed { name: self.name.clone() })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // plugins not interested in the document may exit without
+            // reading it, which only closes the pipe
+            let _ = writeln!(stdin, "{}", document.to_json());
+        }
+        let status = child.wait()
+            .map_err(|_| ErrorKind::PluginCallFailed { name: self.name.clone() })?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(ErrorKind::PluginFailed { name: self.name.clone(), status: status.code() })
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,179 @@
+
+/// Single step of transforming one list of lines into another, holding the
+/// indices of the affected lines
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Edit {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Unified diff with three lines of context between 'old' and 'new', empty if
+/// both are equal
+pub fn unified_diff(old: &[String], new: &[String],
+                    old_name: &str, new_name: &str) -> Vec<String> {
+    const CONTEXT: usize = 3;
+
+    let edits = edit_script(old, new);
+    let changes: Vec<usize> = edits.iter()
+                                   .enumerate()
+                                   .filter(|(_, e)| !matches!(e, Edit::Keep(..)))
+                                   .map(|(i, _)| i)
+                                   .collect();
+    if changes.is_empty() {
+        return Vec::new();
+    }
+
+    // ranges of edits shown in each hunk, merging overlapping contexts
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changes {
+        let start = i.saturating_sub(CONTEXT);
+        let end   = (i + CONTEXT + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _                             => hunks.push((start, end)),
+        }
+    }
+
+    // position in old and new before each edit
+    let mut positions = Vec::new();
+    let (mut o, mut n) = (0, 0);
+    for edit in &edits {
+        positions.push((o, n));
+        match edit {
+            Edit::Keep(..)  => { o += 1; n += 1; },
+            Edit::Delete(_) => o += 1,
+            Edit::Insert(_) => n += 1,
+        }
+    }
+
+    let mut lines = vec![format!("--- {}", old_name), format!("+++ {}", new_name)];
+    for (start, end) in hunks {
+        let mut body = Vec::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        for edit in &edits[start..end] {
+            match *edit {
+                Edit::Keep(i, _) => {
+                    body.push(format!(" {}", old[i]));
+                    old_count += 1;
+                    new_count += 1;
+                },
+                Edit::Delete(i) => {
+                    body.push(format!("-{}", old[i]));
+                    old_count += 1;
+                },
+                Edit::Insert(i) => {
+                    body.push(format!("+{}", new[i]));
+                    new_count += 1;
+                },
+            }
+        }
+        // empty ranges start at the line before them
+        let (o, n) = positions[start];
+        let old_start = if old_count > 0 { o + 1 } else { o };
+        let new_start = if new_count > 0 { n + 1 } else { n };
+        lines.push(format!("@@ -{},{} +{},{} @@", old_start, old_count,
+                                                  new_start, new_count));
+        lines.append(&mut body);
+    }
+    lines
+}
+
+/// Shortest edit script turning 'a' into 'b' (Myers' algorithm in linear
+/// space)
+fn edit_script(a: &[String], b: &[String]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_range(a, b, 0, 0, &mut edits);
+    edits
+}
+
+/// Appends the edits turning 'a' into 'b', which start at the given indices
+/// of the whole lists. The middle snake of a shortest edit path splits the
+/// lists into two smaller ones to compare.
+fn diff_range(a: &[String], b: &[String], a_start: usize, b_start: usize,
+              edits: &mut Vec<Edit>) {
+    // lines common to both at the start and end need no search
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev()
+                            .zip(b[prefix..].iter().rev())
+                            .take_while(|(x, y)| x == y)
+                            .count();
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    edits.extend((0..prefix).map(|i| Edit::Keep(a_start + i, b_start + i)));
+
+    if prefix == a_end {
+        edits.extend((prefix..b_end).map(|i| Edit::Insert(b_start + i)));
+    } else if prefix == b_end {
+        edits.extend((prefix..a_end).map(|i| Edit::Delete(a_start + i)));
+    } else {
+        let (a, b) = (&a[prefix..a_end], &b[prefix..b_end]);
+        let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+        let (x, y, u, v) = middle_snake(a, b);
+        diff_range(&a[..x], &b[..y], a_start, b_start, edits);
+        edits.extend((0..u-x).map(|i| Edit::Keep(a_start + x + i, b_start + y + i)));
+        diff_range(&a[u..], &b[v..], a_start + u, b_start + v, edits);
+    }
+
+    edits.extend((0..suffix).map(|i| Edit::Keep(a_start + a_end + i,
+                                                 b_start + b_end + i)));
+}
+
+/// First and last point (x, y) and (u, v) of the middle snake of a shortest
+/// edit path turning 'a' into 'b', found by searching from both ends at
+/// once. Neither list may be empty.
+fn middle_snake(a: &[String], b: &[String]) -> (usize, usize, usize, usize) {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta  = n - m;
+    let odd    = delta % 2 != 0;
+    let max    = (n + m + 1) / 2;
+    let offset = max + 1;
+    // furthest x reached on each diagonal k = x - y from the start, and from
+    // the end in the reversed lists
+    let mut forward  = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let (x0, x) = snake(&forward, k, d, offset, |x, y| {
+                x < n && y < m && a[x as usize] == b[y as usize]
+            });
+            forward[(k + offset) as usize] = x;
+            // the paths meet if the backward one reached this diagonal
+            // in the previous round and got past this point
+            let back = delta - k;
+            if odd && back.abs() < d && x + backward[(back + offset) as usize] >= n {
+                return (x0 as usize, (x0 - k) as usize, x as usize, (x - k) as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let (x0, x) = snake(&backward, k, d, offset, |x, y| {
+                x < n && y < m && a[(n-1-x) as usize] == b[(m-1-y) as usize]
+            });
+            backward[(k + offset) as usize] = x;
+            let front = delta - k;
+            if !odd && front.abs() <= d && x + forward[(front + offset) as usize] >= n {
+                return ((n - x) as usize, (m - x + k) as usize,
+                        (n - x0) as usize, (m - x0 + k) as usize);
+            }
+        }
+    }
+    unreachable!("an edit path has at most n + m edits")
+}
+
+/// Extends the furthest path with 'd' edits on diagonal 'k' by one edit and
+/// then along equal lines. Returns x before and after the equal lines.
+fn snake<F>(v: &[isize], k: isize, d: isize, offset: isize, equal: F) -> (isize, isize)
+    where F: Fn(isize, isize) -> bool {
+    let i = (k + offset) as usize;
+    let start = if k == -d || (k != d && v[i-1] < v[i+1]) {
+        v[i+1]
+    } else {
+        v[i-1] + 1
+    };
+    let mut x = start;
+    while equal(x, x - k) {
+        x += 1;
+    }
+    (start, x)
+}
@@ -0,0 +1,1328 @@
+use std::fs;
+use std::path::PathBuf;
+
+use error::ErrorKind;
+use file::{read_lines, resolve_path, LineEnding};
+use header::{HeaderArgs, tokenize, unquote};
+use json;
+
+/// Maximum number of nested '#+INCLUDE:'s of org files, not counting the
+/// root file
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Lines of its file an element was read from, starting at 1 and including
+/// the last one
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end:   usize,
+}
+
+/// Headline like '** TODO [#A] Title :tag1:tag2:'
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headline {
+    pub level:    usize,
+    // 'TODO' or 'DONE'
+    pub todo:     Option<String>,
+    pub priority: Option<char>,
+    pub title:    String,
+    pub tags:     Vec<String>,
+    // contents of the property drawer of its section:
+    // (<lowercase name>, <value>)
+    pub properties: Vec<(String, String)>,
+    // the headline's line
+    pub span: Span,
+}
+
+impl Headline {
+    /// Value of the 'CUSTOM_ID' property, which links can refer to
+    pub fn custom_id(&self) -> Option<&str> {
+        self.properties.iter()
+                       .find(|(name, _)| name == "custom_id")
+                       .map(|(_, id)| id.as_str())
+    }
+}
+
+/// Keyword line like '#+TITLE: Notes', except for the ones belonging to the
+/// following element like '#+NAME:'
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyword {
+    // uppercase, without '#+' and colon
+    pub key:   String,
+    pub value: String,
+    pub span:  Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrcBlock {
+    // set via '#+NAME:'
+    pub name:     Option<String>,
+    pub lang:     String,
+    // flags like '-n' or '-i' in the '#+BEGIN_SRC' line
+    pub switches: Vec<String>,
+    // header arguments of the '#+BEGIN_SRC' line
+    pub args:     HeaderArgs,
+    // header arguments of each '#+HEADER:' line
+    pub headers:  Vec<HeaderArgs>,
+    // content without org's comma escapes and, unless the '-i' flag is set,
+    // the common indentation
+    pub lines:    Vec<String>,
+    // indentation removed from each line
+    pub indent:   String,
+    // number of the first line of 'lines'
+    pub line:     usize,
+    pub span:     Span,
+}
+
+impl SrcBlock {
+    /// Header arguments in effect for the block: the inherited ones,
+    /// overridden by those of the '#+BEGIN_SRC' line and then by those of
+    /// '#+HEADER:' lines
+    pub fn header_args(&self, scopes: &Scopes) -> HeaderArgs {
+        let mut args = scopes.header_args(&self.lang);
+        args.merge(&self.args);
+        for header in &self.headers {
+            args.merge(header);
+        }
+        args
+    }
+
+    /// Whether exporting the document shows the block's code. Like
+    /// org-babel without evaluating anything, so results are never shown.
+    pub fn exported(&self, scopes: &Scopes) -> bool {
+        let args = self.header_args(scopes);
+        let exports = args.get("exports").unwrap_or("code");
+        exports != "none" && exports != "results"
+    }
+}
+
+/// '#+BEGIN_EXAMPLE' block or the content of an 'example' include
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExampleBlock {
+    pub name:  Option<String>,
+    pub lines: Vec<String>,
+    // number of the first line of 'lines'
+    pub line:  usize,
+    pub span:  Span,
+}
+
+/// Any other block like '#+BEGIN_QUOTE' or '#+BEGIN_EXPORT latex'
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    // lowercase, e.g. 'quote'
+    pub kind:   String,
+    // rest of the '#+BEGIN_' line
+    pub params: String,
+    pub lines:  Vec<String>,
+    pub span:   Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableRow {
+    Cells(Vec<String>),
+    // horizontal line like '|---+---|'
+    Rule,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub name: Option<String>,
+    pub rows: Vec<TableRow>,
+    pub span: Span,
+}
+
+impl Table {
+    /// Rows containing cells, leaving out rules
+    pub fn cell_rows(&self) -> Vec<&Vec<String>> {
+        self.rows.iter()
+                 .filter_map(|r| match r {
+                     TableRow::Cells(cells) => Some(cells),
+                     TableRow::Rule         => None,
+                 })
+                 .collect()
+    }
+
+    /// Number of columns of the widest row
+    pub fn columns(&self) -> usize {
+        self.cell_rows().iter().map(|r| r.len()).max().unwrap_or(0)
+    }
+
+    /// Whether each column contains mostly numbers, which org aligns to the
+    /// right. The first 'skip' rows, e.g. a header, are not considered.
+    pub fn numeric_columns(&self, skip: usize) -> Vec<bool> {
+        let rows = self.cell_rows();
+        let rows = &rows[skip.min(rows.len())..];
+        (0..self.columns()).map(|c| {
+            let cells: Vec<&str> = rows.iter()
+                                       .filter_map(|r| r.get(c).map(|s| s.trim()))
+                                       .filter(|s| !s.is_empty())
+                                       .collect();
+            let numbers = cells.iter().filter(|s| s.parse::<f64>().is_ok()).count();
+            numbers * 2 > cells.len()
+        }).collect()
+    }
+}
+
+/// Consecutive lines of text
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paragraph {
+    pub lines: Vec<String>,
+    pub span:  Span,
+}
+
+/// Item of a plain list like '- text', '1. text', '- [X] done' or
+/// '- term :: description'
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListItem {
+    // column of the bullet, nested items having a larger one
+    pub indent:   usize,
+    // '-', '+' or '*', or the counter of ordered lists like '1.' or '2)'
+    pub bullet:   String,
+    // state of a checkbox: ' ', 'X' or '-'
+    pub checkbox: Option<char>,
+    // term of a description item
+    pub tag:      Option<String>,
+    // text following the bullet and the item's further lines without their
+    // indentation, empty lines separating paragraphs
+    pub lines:    Vec<String>,
+    pub span:     Span,
+}
+
+impl ListItem {
+    /// Whether the item belongs to a numbered list
+    pub fn ordered(&self) -> bool {
+        self.bullet.ends_with(['.', ')'])
+    }
+}
+
+/// Consecutive list items, including nested ones. Blocks or tables inside an
+/// item end the list and follow it as elements of their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct List {
+    pub items: Vec<ListItem>,
+    // the lines as found in the file
+    pub lines: Vec<String>,
+    pub span:  Span,
+}
+
+/// '#+INCLUDE:' line like '"lib.py" src python :lines "10-40" :tangle lib.py'
+#[derive(Clone, Debug, PartialEq)]
+pub struct Include {
+    // as given, relative to the including file
+    pub path: String,
+    // part of an org file following '::' in the path: '*Heading',
+    // '#custom-id' or a block name
+    pub target: Option<String>,
+    // 'src', 'example', 'export' etc., empty for org files
+    pub kind: String,
+    // language of 'src' and backend of 'export' includes
+    pub lang: String,
+    // first line and the line after the last one, starting at 1
+    pub lines: (usize, Option<usize>),
+    // level the topmost headlines of an included org file are moved to
+    pub minlevel: Option<usize>,
+    pub args: HeaderArgs,
+    pub name: Option<String>,
+    // the included part of the file, filled in by 'Document::from_file'.
+    // 'src' and 'example' includes consist of a single block. None for
+    // other kinds and org files already included elsewhere.
+    pub content: Option<Document>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Headline(Headline),
+    Keyword(Keyword),
+    Src(SrcBlock),
+    Example(ExampleBlock),
+    Block(Block),
+    Table(Table),
+    Paragraph(Paragraph),
+    List(List),
+    Include(Include),
+}
+
+/// Parsed org file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    pub path:        String,
+    pub line_ending: LineEnding,
+    // property drawer in front of the first headline
+    pub properties:  Vec<(String, String)>,
+    // elements in the order of the file, headlines being elements like all
+    // others
+    pub elements:    Vec<Element>,
+}
+
+/// Org properties in effect at some point of a document: those of the file
+/// (level 0) and of each enclosing headline, outermost first
+#[derive(Clone, Debug, Default)]
+pub struct Scopes {
+    // (<level>, [(<lowercase name>, <value>)])
+    scopes: Vec<(usize, Vec<(String, String)>)>,
+}
+
+impl Scopes {
+    /// Properties at the start of a document whose headlines are nested in
+    /// the 'outer' ones, e.g. of the file including it. '#+PROPERTY:' lines
+    /// apply to the whole file containing them, so the ones of an included
+    /// file only affect its own blocks.
+    pub fn enter_document(outer: &Scopes, document: &Document) -> Scopes {
+        let mut scopes = outer.clone();
+        if scopes.scopes.is_empty() {
+            scopes.scopes.push((0, Vec::new()));
+        }
+        for element in &document.elements {
+            if let Element::Keyword(k) = element {
+                if k.key == "PROPERTY" {
+                    scopes.scopes[0].1.push(parse_property(&k.value));
+                }
+            }
+        }
+        let len = scopes.scopes.len();
+        scopes.scopes[len-1].1.extend(document.properties.iter().cloned());
+        scopes
+    }
+
+    /// Enters the section of a headline, leaving the ones of the same or a
+    /// deeper level
+    pub fn enter_headline(&mut self, headline: &Headline) {
+        while self.scopes.len() > 1 && self.scopes[self.scopes.len()-1].0 >= headline.level {
+            self.scopes.pop();
+        }
+        self.scopes.push((headline.level, headline.properties.clone()));
+    }
+
+    /// Value of a property as org inherits it: the innermost definition
+    /// wins, definitions of '<name>+' extend the value inherited so far
+    fn property(&self, name: &str) -> String {
+        let extended  = format!("{}+", name);
+        let mut value = String::new();
+        for (_, properties) in &self.scopes {
+            for (n, v) in properties {
+                if n == name {
+                    value = v.clone();
+                } else if n == &extended {
+                    value.push(' ');
+                    value.push_str(v);
+                }
+            }
+        }
+        value
+    }
+
+    /// Header arguments a block of the given language inherits from the
+    /// org-babel defaults, '#+PROPERTY:' lines and property drawers
+    pub fn header_args(&self, lang: &str) -> HeaderArgs {
+        let generic  = self.property("header-args");
+        let specific = self.property(&format!("header-args:{}", lang.to_lowercase()));
+        let mut args = HeaderArgs::defaults();
+        args.merge(&HeaderArgs::parse(&generic));
+        args.merge(&HeaderArgs::parse(&specific));
+        args
+    }
+}
+
+/// Org files included while parsing a document
+#[derive(Clone, Debug, Default)]
+struct Includes {
+    // files currently being parsed, outermost first:
+    // (<absolute path>, <path as given>)
+    chain: Vec<(PathBuf, String)>,
+    // files parsed so far: (<absolute path>, <included part>)
+    seen:  Vec<(PathBuf, String)>,
+}
+
+impl Includes {
+    /// Marks a file as being parsed, failing if it is already being parsed
+    /// or the include depth limit is reached. 'part' describes which part of
+    /// the file is included, empty for all of it.
+    fn enter(&mut self, path: &str, part: &str) -> Result<(), ErrorKind> {
+        let absolute = absolute_path(path);
+        if let Some(i) = self.chain.iter().position(|f| f.0 == absolute) {
+            let mut chain: Vec<String> = self.chain[i..].iter()
+                                                        .map(|f| f.1.clone())
+                                                        .collect();
+            chain.push(path.to_string());
+            return Err(ErrorKind::CyclicInclude { chain });
+        }
+        // the chain holds the files including this one, so its length is
+        // the nesting depth of the file, 0 for the root file
+        let depth = self.chain.len();
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(ErrorKind::IncludeTooDeep { path: path.to_string(),
+                                                   limit: MAX_INCLUDE_DEPTH });
+        }
+        self.chain.push((absolute.clone(), path.to_string()));
+        self.seen.push((absolute, part.to_string()));
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.chain.pop();
+    }
+
+    /// Whether the part of the file was parsed before, but the file isn't
+    /// being parsed right now
+    fn done(&self, path: &str, part: &str) -> bool {
+        let absolute = absolute_path(path);
+        self.seen.iter().any(|f| f.0 == absolute && f.1 == part) &&
+        !self.chain.iter().any(|f| f.0 == absolute)
+    }
+}
+
+impl Document {
+    /// Reads and parses an org file along with the files it includes
+    pub fn from_file(path: &str) -> Result<Self, ErrorKind> {
+        let mut includes = Includes::default();
+        let (lines, ending) = read_lines(path)?;
+        includes.enter(path, "")?;
+        let mut document = Document::parse(path, &lines, 1, ending);
+        document.resolve_includes(&mut includes)?;
+        Ok(document)
+    }
+
+    /// Parses the lines of an org file, starting at line 'first_line',
+    /// without reading included files
+    pub fn parse(path: &str, lines: &[String], first_line: usize,
+                 line_ending: LineEnding) -> Self {
+        let mut document = Document {
+            path: path.to_string(),
+            line_ending,
+            properties: Vec::new(),
+            elements: Vec::new(),
+        };
+        // keywords belonging to the next element, dropped by any element not
+        // taking them
+        let mut name    = None;
+        let mut headers = Vec::new();
+        let mut text: Vec<String> = Vec::new();
+        let mut text_start = 0;
+
+        let span = |start: usize, end: usize| Span { start: first_line + start,
+                                                     end:   first_line + end };
+        let mut i = 0;
+        while i < lines.len() {
+            let line = &lines[i];
+            let trimmed = line.trim();
+
+            // text ends at anything but text
+            let is_text = is_text_line(line) && list_item(line).is_none();
+            if is_text {
+                if text.is_empty() {
+                    text_start = i;
+                }
+                text.push(line.clone());
+                i += 1;
+                continue;
+            }
+            if !text.is_empty() {
+                document.elements.push(Element::Paragraph(Paragraph {
+                    lines: text.clone(),
+                    span:  span(text_start, i-1),
+                }));
+                text.clear();
+                name = None;
+                headers.clear();
+            }
+
+            if let Some((kind, params)) = begin_block(line) {
+                let end = match block_end(lines, i, &kind) {
+                    Some(e) => e,
+                    // without its end, the line is no block at all
+                    None    => { i += 1; continue; },
+                };
+                let mut content: Vec<String> = lines[i+1..end].iter()
+                                                              .map(|l| unescape_line(l))
+                                                              .collect();
+                let element = match kind.as_str() {
+                    "src" => {
+                        let (lang, switches, args) = parse_begin_src(&params);
+                        let indent = if switches.iter().any(|s| s == "-i") {
+                            String::new()
+                        } else {
+                            remove_indentation(&mut content)
+                        };
+                        Element::Src(SrcBlock {
+                            name: name.take(),
+                            lang,
+                            switches,
+                            args,
+                            headers: headers.split_off(0),
+                            lines: content,
+                            indent,
+                            line: first_line + i + 1,
+                            span: span(i, end),
+                        })
+                    },
+                    "example" => {
+                        remove_indentation(&mut content);
+                        Element::Example(ExampleBlock {
+                            name:  name.take(),
+                            lines: content,
+                            line:  first_line + i + 1,
+                            span:  span(i, end),
+                        })
+                    },
+                    _ => Element::Block(Block {
+                        kind,
+                        params,
+                        lines: content,
+                        span:  span(i, end),
+                    }),
+                };
+                document.elements.push(element);
+                name = None;
+                headers.clear();
+                i = end + 1;
+                continue;
+            }
+
+            if list_item(line).is_some() {
+                let (list, end) = parse_list(lines, i, first_line);
+                document.elements.push(Element::List(list));
+                name = None;
+                headers.clear();
+                i = end;
+                continue;
+            }
+
+            if let Some(level) = headline_level(line) {
+                document.elements.push(Element::Headline(parse_headline(line, level,
+                                                                        span(i, i))));
+                name = None;
+                headers.clear();
+            } else if trimmed.eq_ignore_ascii_case(":PROPERTIES:") {
+                let end = lines[i..].iter()
+                                    .position(|l| l.trim().eq_ignore_ascii_case(":END:"))
+                                    .map_or(lines.len(), |e| i + e);
+                let properties = lines[i+1..end].iter().filter_map(|l| parse_node_property(l));
+                // properties belong to the section they are in
+                let section = document.elements.iter_mut().rev().find_map(|e| match e {
+                    Element::Headline(h) => Some(h),
+                    _                    => None,
+                });
+                match section {
+                    Some(h) => h.properties.extend(properties),
+                    None    => document.properties.extend(properties),
+                }
+                i = end;
+            } else if trimmed.starts_with('|') {
+                let end = lines[i..].iter()
+                                    .position(|l| !l.trim().starts_with('|'))
+                                    .map_or(lines.len(), |e| i + e);
+                document.elements.push(Element::Table(Table {
+                    name: name.take(),
+                    rows: lines[i..end].iter().map(|l| parse_table_row(l)).collect(),
+                    span: span(i, end-1),
+                }));
+                headers.clear();
+                i = end;
+                continue;
+            } else if let Some(args) = keyword(line, "#+HEADER:")
+                                           .or_else(|| keyword(line, "#+HEADERS:")) {
+                headers.push(HeaderArgs::parse(args));
+            } else if let Some(args) = keyword(line, "#+NAME:") {
+                name = Some(args.trim().to_string());
+            } else if let Some(args) = keyword(line, "#+INCLUDE:") {
+                if let Some(mut include) = Include::parse(args, span(i, i)) {
+                    include.name = name.take();
+                    document.elements.push(Element::Include(include));
+                }
+                headers.clear();
+            } else if let Some((key, value)) = parse_keyword(line) {
+                // like '#+NAME:', captions and attributes belong to the next
+                // element
+                if !is_affiliated(&key) {
+                    name = None;
+                    headers.clear();
+                }
+                document.elements.push(Element::Keyword(Keyword {
+                    key,
+                    value,
+                    span: span(i, i),
+                }));
+            }
+            i += 1;
+        }
+        if !text.is_empty() {
+            document.elements.push(Element::Paragraph(Paragraph {
+                lines: text,
+                span:  span(text_start, lines.len()-1),
+            }));
+        }
+        document
+    }
+
+    /// Value of the last keyword with the given name, e.g. 'TITLE'
+    pub fn keyword(&self, key: &str) -> Option<&str> {
+        self.elements.iter()
+                     .filter_map(|e| match e {
+                         Element::Keyword(k) if k.key.eq_ignore_ascii_case(key) => Some(k.value.as_str()),
+                         _ => None,
+                     })
+                     .next_back()
+    }
+
+    /// All headlines of the document, not including those of included files
+    pub fn headlines(&self) -> Vec<&Headline> {
+        self.elements.iter()
+                     .filter_map(|e| match e {
+                         Element::Headline(h) => Some(h),
+                         _                    => None,
+                     })
+                     .collect()
+    }
+
+    /// Elements to export, leaving out the subtrees of headlines tagged with
+    /// 'noexport' or starting with 'COMMENT'
+    pub fn exported(&self) -> Vec<&Element> {
+        let mut result = Vec::new();
+        // level of the headline being left out
+        let mut skipped: Option<usize> = None;
+        for element in &self.elements {
+            if let Element::Headline(h) = element {
+                if skipped.is_some_and(|level| h.level <= level) {
+                    skipped = None;
+                }
+                if skipped.is_none() && (h.tags.iter().any(|t| t == "noexport") ||
+                                         h.title == "COMMENT" ||
+                                         h.title.starts_with("COMMENT ")) {
+                    skipped = Some(h.level);
+                }
+            }
+            if skipped.is_none() {
+                result.push(element);
+            }
+        }
+        result
+    }
+
+    /// All source blocks of the document and its included files
+    pub fn src_blocks(&self) -> Vec<&SrcBlock> {
+        let mut blocks = Vec::new();
+        for element in &self.elements {
+            match element {
+                Element::Src(b) => blocks.push(b),
+                Element::Include(Include { content: Some(d), .. }) => {
+                    blocks.append(&mut d.src_blocks());
+                },
+                _ => {},
+            }
+        }
+        blocks
+    }
+
+    /// The document as JSON, e.g. for external backends. Elements are
+    /// objects with a "type" like "headline" or "src" and fields named like
+    /// the ones of the types above, spans are arrays of first and last line.
+    /// Source blocks also have "header_args", their arguments as inherited.
+    pub fn to_json(&self) -> String {
+        self.json(&Scopes::default())
+    }
+
+    /// JSON of a document whose headlines are nested in the 'outer' ones
+    fn json(&self, outer: &Scopes) -> String {
+        let mut scopes = Scopes::enter_document(outer, self);
+        let mut elements = Vec::new();
+        for element in &self.elements {
+            if let Element::Headline(h) = element {
+                scopes.enter_headline(h);
+            }
+            elements.push(element.to_json(&scopes));
+        }
+        let ending = match self.line_ending {
+            LineEnding::Lf   => "lf",
+            LineEnding::CrLf => "crlf",
+        };
+        json::object(&[
+            ("path",        json::string(&self.path)),
+            ("line_ending", json::string(ending)),
+            ("properties",  json::pairs(&self.properties)),
+            ("elements",    json::array(elements)),
+        ])
+    }
+
+    /// Reads the files included by the document and stores them in the
+    /// 'content' of each include
+    fn resolve_includes(&mut self, includes: &mut Includes) -> Result<(), ErrorKind> {
+        let path = self.path.clone();
+        for element in self.elements.iter_mut() {
+            let include = match element {
+                Element::Include(i) => i,
+                _                   => continue,
+            };
+            let included_filename = resolve_path(&path, &include.path);
+
+            include.content = match include.kind.as_str() {
+                "" => {
+                    // a file included along several paths contributes its
+                    // blocks only once
+                    let part = format!("{}:{}", include.target.as_deref().unwrap_or(""),
+                                       include.args.get("lines").unwrap_or(""));
+                    if includes.done(&included_filename, &part) {
+                        continue;
+                    }
+                    let (lines, ending) = read_lines(&included_filename)?;
+                    // ':lines' counts from the start of the target, if any
+                    let (start, end) = match &include.target {
+                        Some(t) => target_range(&lines, t,
+                                                include.args.get("only-contents")
+                                                       .is_some_and(|v| v != "nil"))
+                                        .ok_or_else(|| ErrorKind::IncludeTargetNotFound {
+                                            path:   included_filename.clone(),
+                                            target: t.clone(),
+                                        })?,
+                        None    => (0, lines.len()),
+                    };
+                    let (selected, first_line) = include.select(&lines[start..end]);
+                    let shifted = match include.minlevel {
+                        Some(level) => shift_headlines(selected, level),
+                        None        => selected.to_vec(),
+                    };
+                    includes.enter(&included_filename, &part)?;
+                    let mut document = Document::parse(&included_filename, &shifted,
+                                                       first_line + start, ending);
+                    let result = document.resolve_includes(includes);
+                    includes.leave();
+                    result?;
+                    Some(document)
+                },
+                "src" | "example" => {
+                    let (lines, ending) = read_lines(&included_filename)?;
+                    let (selected, first_line) = include.select(&lines);
+                    let span = Span { start: first_line,
+                                      end:   first_line + selected.len().max(1) - 1 };
+                    let element = if include.kind == "src" {
+                        Element::Src(SrcBlock {
+                            name:     include.name.clone(),
+                            lang:     include.lang.clone(),
+                            switches: Vec::new(),
+                            args:     include.args.clone(),
+                            headers:  Vec::new(),
+                            lines:    selected.to_vec(),
+                            indent:   String::new(),
+                            line:     first_line,
+                            span,
+                        })
+                    } else {
+                        Element::Example(ExampleBlock {
+                            name:  include.name.clone(),
+                            lines: selected.to_vec(),
+                            line:  first_line,
+                            span,
+                        })
+                    };
+                    Some(Document {
+                        path: included_filename,
+                        line_ending: ending,
+                        properties: Vec::new(),
+                        elements: vec![element],
+                    })
+                },
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+}
+
+impl Element {
+    /// JSON of the element, 'scopes' being the ones it is in
+    fn to_json(&self, scopes: &Scopes) -> String {
+        let span  = |s: &Span| json::array(vec![s.start.to_string(), s.end.to_string()]);
+        let lines = |l: &[String]| json::array(l.iter().map(|l| json::string(l)));
+        let name  = |n: &Option<String>| json::optional(n.as_deref());
+        match self {
+            Element::Headline(h) => json::object(&[
+                ("type",       json::string("headline")),
+                ("level",      h.level.to_string()),
+                ("todo",       json::optional(h.todo.as_deref())),
+                ("priority",   json::optional(h.priority.map(|p| p.to_string()).as_deref())),
+                ("title",      json::string(&h.title)),
+                ("tags",       lines(&h.tags)),
+                ("properties", json::pairs(&h.properties)),
+                ("span",       span(&h.span)),
+            ]),
+            Element::Keyword(k) => json::object(&[
+                ("type",  json::string("keyword")),
+                ("key",   json::string(&k.key)),
+                ("value", json::string(&k.value)),
+                ("span",  span(&k.span)),
+            ]),
+            Element::Src(b) => json::object(&[
+                ("type",        json::string("src")),
+                ("name",        name(&b.name)),
+                ("lang",        json::string(&b.lang)),
+                ("switches",    lines(&b.switches)),
+                ("args",        json::pairs(b.args.iter())),
+                ("headers",     json::array(b.headers.iter().map(|h| json::pairs(h.iter())))),
+                ("header_args", json::pairs(b.header_args(scopes).iter())),
+                ("lines",       lines(&b.lines)),
+                ("indent",      json::string(&b.indent)),
+                ("line",        b.line.to_string()),
+                ("span",        span(&b.span)),
+            ]),
+            Element::Example(b) => json::object(&[
+                ("type",  json::string("example")),
+                ("name",  name(&b.name)),
+                ("lines", lines(&b.lines)),
+                ("line",  b.line.to_string()),
+                ("span",  span(&b.span)),
+            ]),
+            Element::Block(b) => json::object(&[
+                ("type",   json::string("block")),
+                ("kind",   json::string(&b.kind)),
+                ("params", json::string(&b.params)),
+                ("lines",  lines(&b.lines)),
+                ("span",   span(&b.span)),
+            ]),
+            // horizontal rules are null
+            Element::Table(t) => json::object(&[
+                ("type", json::string("table")),
+                ("name", name(&t.name)),
+                ("rows", json::array(t.rows.iter().map(|r| match r {
+                    TableRow::Cells(cells) => lines(cells),
+                    TableRow::Rule         => "null".to_string(),
+                }))),
+                ("span", span(&t.span)),
+            ]),
+            Element::Paragraph(p) => json::object(&[
+                ("type",  json::string("paragraph")),
+                ("lines", lines(&p.lines)),
+                ("span",  span(&p.span)),
+            ]),
+            Element::List(l) => json::object(&[
+                ("type",  json::string("list")),
+                ("items", json::array(l.items.iter().map(|item| json::object(&[
+                    ("indent",   item.indent.to_string()),
+                    ("bullet",   json::string(&item.bullet)),
+                    ("checkbox", json::optional(item.checkbox.map(|c| c.to_string()).as_deref())),
+                    ("tag",      name(&item.tag)),
+                    ("lines",    lines(&item.lines)),
+                    ("span",     span(&item.span)),
+                ])))),
+                ("lines", lines(&l.lines)),
+                ("span",  span(&l.span)),
+            ]),
+            Element::Include(i) => json::object(&[
+                ("type",     json::string("include")),
+                ("path",     json::string(&i.path)),
+                ("target",   json::optional(i.target.as_deref())),
+                ("kind",     json::string(&i.kind)),
+                ("lang",     json::string(&i.lang)),
+                ("lines",    json::array(vec![i.lines.0.to_string(),
+                                              i.lines.1.map_or("null".to_string(), |l| l.to_string())])),
+                ("minlevel", i.minlevel.map_or("null".to_string(), |l| l.to_string())),
+                ("args",     json::pairs(i.args.iter())),
+                ("name",     name(&i.name)),
+                ("content",  i.content.as_ref().map_or("null".to_string(), |d| d.json(scopes))),
+                ("span",     span(&i.span)),
+            ]),
+        }
+    }
+}
+
+impl Include {
+    fn parse(line: &str, span: Span) -> Option<Self> {
+        let tokens = tokenize(line);
+        let path = unquote(tokens.first()?);
+        let (path, target) = match path.split_once("::") {
+            Some((p, t)) => (p.to_string(), Some(t.to_string())),
+            None         => (path, None),
+        };
+        // kind and language come before the first argument name
+        let mut positional = tokens[1..].iter()
+                                        .take_while(|t| !t.starts_with(':'));
+        let kind = positional.next().map(|k| k.to_lowercase()).unwrap_or_default();
+        let lang = positional.next().cloned().unwrap_or_default();
+
+        let args = HeaderArgs::parse(line);
+        let lines = args.get("lines")
+                        .and_then(Include::parse_lines)
+                        .unwrap_or((1, None));
+        let minlevel = args.get("minlevel").and_then(|l| l.parse().ok());
+        Some(Include { path, target, kind, lang, lines, minlevel, args,
+                       name: None, content: None, span })
+    }
+
+    /// Parses a range like '10-40', '10-' or '-40', where as in org the last
+    /// line is excluded
+    fn parse_lines(range: &str) -> Option<(usize, Option<usize>)> {
+        let (first, end) = range.trim().split_once('-')?;
+        let first = if first.is_empty() { 1 } else { first.trim().parse().ok()? };
+        let end   = if end.is_empty() { None } else { Some(end.trim().parse().ok()?) };
+        Some((first.max(1), end))
+    }
+
+    /// The included part of a file's lines along with the number of the first
+    /// one
+    fn select<'a>(&self, lines: &'a [String]) -> (&'a [String], usize) {
+        let start = (self.lines.0 - 1).min(lines.len());
+        // an end of 0 or 1 selects nothing, like in org
+        let end   = self.lines.1.map_or(lines.len(), |e| e.saturating_sub(1).clamp(start, lines.len()));
+        (&lines[start..end], start + 1)
+    }
+}
+
+/// Absolute version of a path if it exists, to compare paths written
+/// differently
+fn absolute_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// Returns the rest of the line if it starts with the given keyword like
+/// '#+NAME:', ignoring case and indentation
+pub fn keyword<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let trimmed = line.trim_start();
+    let head    = trimmed.get(..keyword.len())?;
+    if !head.eq_ignore_ascii_case(keyword) {
+        return None;
+    }
+    let rest = &trimmed[keyword.len()..];
+    // keywords without colon have to be followed by whitespace
+    if !keyword.ends_with(':') &&
+       !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some(rest)
+}
+
+/// Splits a line like '#+TITLE: Notes' into uppercase key and value
+fn parse_keyword(line: &str) -> Option<(String, String)> {
+    let rest  = line.trim_start().strip_prefix("#+")?;
+    let colon = rest.find(':')?;
+    let key   = &rest[..colon];
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key.to_uppercase(), rest[colon+1..].trim().to_string()))
+}
+
+/// Whether a keyword like '#+CAPTION:' describes the element after it
+fn is_affiliated(key: &str) -> bool {
+    ["CAPTION", "PLOT", "RESULTS"].contains(&key) || key.starts_with("ATTR_")
+}
+
+/// Type and parameters of a line starting a block like '#+BEGIN_SRC python'
+fn begin_block(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim_start();
+    if !trimmed.get(..8)?.eq_ignore_ascii_case("#+BEGIN_") {
+        return None;
+    }
+    let rest = &trimmed[8..];
+    let kind_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    if kind_len == 0 {
+        return None;
+    }
+    Some((rest[..kind_len].to_lowercase(), rest[kind_len..].trim().to_string()))
+}
+
+/// Index of the line ending the block of the given type started at 'start'
+fn block_end(lines: &[String], start: usize, kind: &str) -> Option<usize> {
+    let end = format!("#+END_{}", kind);
+    lines[start+1..].iter()
+                    .position(|l| keyword(l, &end).is_some_and(|r| r.trim().is_empty()))
+                    .map(|i| start + 1 + i)
+}
+
+/// Line of text, i.e. not empty and not starting any other element
+fn is_text_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with("#+") &&
+    !trimmed.starts_with('|') && !is_comment(trimmed) &&
+    headline_level(line).is_none() &&
+    !trimmed.eq_ignore_ascii_case(":PROPERTIES:")
+}
+
+/// Splits a line starting a list item into the column of its bullet, the
+/// bullet and the rest of the line
+fn list_item(line: &str) -> Option<(usize, &str, &str)> {
+    let trimmed = line.trim_start();
+    let indent  = line.len() - trimmed.len();
+    let counter = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    let bullet_len = match trimmed.chars().next()? {
+        '-' | '+'             => 1,
+        // at the start of a line, stars make a headline
+        '*' if indent > 0     => 1,
+        _ if counter > 0 && counter < 10 &&
+             trimmed[counter..].starts_with(['.', ')']) => counter + 1,
+        _                     => return None,
+    };
+    let rest = &trimmed[bullet_len..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((indent, &trimmed[..bullet_len], rest.trim_start()))
+}
+
+/// Parses the list starting at line 'start', returning it along with the
+/// index of the line after it. 'lines' start at line 'first_line' of the file.
+fn parse_list(lines: &[String], start: usize, first_line: usize) -> (List, usize) {
+    let base = lines[start].len() - lines[start].trim_start().len();
+    let mut items: Vec<ListItem> = Vec::new();
+    let mut end = start;
+    let mut i   = start;
+    while i < lines.len() {
+        let line   = &lines[i];
+        let indent = line.len() - line.trim_start().len();
+        if line.trim().is_empty() {
+            // a single empty line doesn't end the list if it continues
+            // afterwards
+            let continues = lines.get(i+1).is_some_and(|next| {
+                let next_indent = next.len() - next.trim_start().len();
+                match list_item(next) {
+                    Some((n, _, _)) => n >= base,
+                    None            => next_indent > base && is_text_line(next),
+                }
+            });
+            if !continues {
+                break;
+            }
+            i += 1;
+            continue;
+        }
+
+        if let Some((indent, bullet, rest)) = list_item(line) {
+            if indent < base {
+                break;
+            }
+            let (checkbox, rest) = match rest.get(..3) {
+                Some("[ ]") => (Some(' '), &rest[3..]),
+                Some("[X]") | Some("[x]") => (Some('X'), &rest[3..]),
+                Some("[-]") => (Some('-'), &rest[3..]),
+                _           => (None, rest),
+            };
+            let rest = rest.trim_start();
+            let ordered = bullet.ends_with(['.', ')']);
+            let (tag, text) = match rest.split_once(" :: ") {
+                Some((tag, text)) if !ordered => (Some(tag.trim().to_string()), text.trim()),
+                _ => match rest.strip_suffix(" ::") {
+                    Some(tag) if !ordered => (Some(tag.trim().to_string()), ""),
+                    _                     => (None, rest),
+                },
+            };
+            items.push(ListItem {
+                indent,
+                bullet:   bullet.to_string(),
+                checkbox,
+                tag,
+                lines:    vec![text.to_string()],
+                span:     Span { start: i, end: i },
+            });
+        } else if indent > base && is_text_line(line) {
+            // further lines belong to the innermost item they are indented
+            // below
+            let item = items.iter_mut().rev()
+                            .find(|item| item.indent < indent)
+                            .expect("list starts with an item");
+            if item.span.end + 1 < i {
+                item.lines.push(String::new());
+            }
+            item.lines.push(line.trim().to_string());
+            item.span.end = i;
+        } else {
+            break;
+        }
+        end = i;
+        i += 1;
+    }
+
+    for item in items.iter_mut() {
+        item.span.start += first_line;
+        item.span.end   += first_line;
+    }
+    let list = List {
+        items,
+        lines: lines[start..=end].to_vec(),
+        span:  Span { start: first_line + start, end: first_line + end },
+    };
+    (list, end + 1)
+}
+
+/// Org comment line like '# note'
+fn is_comment(trimmed: &str) -> bool {
+    trimmed == "#" || trimmed.starts_with("# ")
+}
+
+/// Returns the level of a headline, i.e. its number of stars
+pub fn headline_level(line: &str) -> Option<usize> {
+    let stars = line.chars().take_while(|c| *c == '*').count();
+    if stars > 0 && (line.len() == stars || line[stars..].starts_with(' ')) {
+        Some(stars)
+    } else {
+        None
+    }
+}
+
+/// Splits a headline into TODO keyword, priority, title and tags
+fn parse_headline(line: &str, level: usize, span: Span) -> Headline {
+    let mut title = line.trim_start_matches('*').trim();
+    let mut todo  = None;
+    for keyword in &["TODO", "DONE"] {
+        if let Some(rest) = title.strip_prefix(keyword) {
+            if rest.is_empty() || rest.starts_with(' ') {
+                todo  = Some(keyword.to_string());
+                title = rest.trim_start();
+            }
+        }
+    }
+    let mut priority = None;
+    if title.starts_with("[#") && title[2..].find(']') == Some(1) {
+        priority = title[2..].chars().next();
+        title = title[4..].trim_start();
+    }
+    // tags like ':foo:bar:' at the end of the line, separated from the
+    // title by spaces or tabs as in org
+    let mut tags = Vec::new();
+    if title.ends_with(':') {
+        let start = title.rfind([' ', '\t']).map_or(0, |i| i + 1);
+        let candidate = &title[start..];
+        if candidate.len() > 1 && candidate.starts_with(':') &&
+           !candidate.contains("::") {
+            tags = candidate.split(':')
+                            .filter(|t| !t.is_empty())
+                            .map(|t| t.to_string())
+                            .collect();
+            title = title[..start].trim_end();
+        }
+    }
+    Headline {
+        level,
+        todo,
+        priority,
+        title: title.to_string(),
+        tags,
+        properties: Vec::new(),
+        span,
+    }
+}
+
+/// Returns the title of a headline without stars, TODO keyword,
+/// priority and tags
+pub fn headline_title(line: &str) -> String {
+    let level = headline_level(line).unwrap_or(0);
+    parse_headline(line, level, Span { start: 0, end: 0 }).title
+}
+
+/// Parses a line like ':header-args:python: :noweb yes' inside a
+/// property drawer
+fn parse_node_property(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim().strip_prefix(':')?;
+    let (name, value) = match trimmed.find(char::is_whitespace) {
+        Some(i) => (&trimmed[..i], trimmed[i..].trim()),
+        None    => (trimmed, ""),
+    };
+    let name = name.strip_suffix(':')?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_lowercase(), value.to_string()))
+}
+
+/// Splits the value of a '#+PROPERTY:' line into lowercase name and value
+fn parse_property(value: &str) -> (String, String) {
+    let value = value.trim();
+    let (name, value) = match value.find(char::is_whitespace) {
+        Some(i) => (&value[..i], value[i..].trim()),
+        None    => (value, ""),
+    };
+    (name.to_lowercase(), value.to_string())
+}
+
+/// Splits a table line into its cells
+fn parse_table_row(line: &str) -> TableRow {
+    let trimmed = line.trim();
+    if trimmed.starts_with("|-") {
+        return TableRow::Rule;
+    }
+    let inner = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    TableRow::Cells(inner.split('|').map(|c| c.trim().to_string()).collect())
+}
+
+/// Removes the indentation common to all non-blank lines of a block and
+/// returns it
+fn remove_indentation(lines: &mut [String]) -> String {
+    let mut common: Option<&str> = None;
+    for line in lines.iter() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = &line[..line.len()-line.trim_start().len()];
+        common = Some(match common {
+            None    => indent,
+            Some(c) => {
+                // length in bytes, indentation may contain non-ASCII spaces
+                let len: usize = c.chars().zip(indent.chars())
+                                          .take_while(|(a, b)| a == b)
+                                          .map(|(a, _)| a.len_utf8())
+                                          .sum();
+                &c[..len]
+            },
+        });
+    }
+
+    let common = common.unwrap_or("").to_string();
+    for line in lines.iter_mut() {
+        if line.starts_with(&common) {
+            *line = line[common.len()..].to_string();
+        } else if line.trim().is_empty() {
+            line.clear();
+        }
+    }
+    common
+}
+
+/// Returns language, flags and header arguments given in a '#+BEGIN_SRC'
+/// line
+fn parse_begin_src(args: &str) -> (String, Vec<String>, HeaderArgs) {
+    let words: Vec<&str> = args.split_whitespace()
+                               .take_while(|w| !w.starts_with(':'))
+                               .collect();
+    let lang = words.first()
+                    .filter(|w| !w.starts_with('-') && !w.starts_with('+'))
+                    .map(|w| w.to_string())
+                    .unwrap_or_default();
+    let switches = words.iter()
+                        .skip(if lang.is_empty() { 0 } else { 1 })
+                        .map(|w| w.to_string())
+                        .collect();
+    (lang, switches, HeaderArgs::parse(args))
+}
+
+/// Removes the comma org puts in front of lines inside a block which
+/// would otherwise be read as headline or keyword, e.g. ',* item' or
+/// ',#+END_SRC'. Of multiple commas only the first one is removed.
+pub fn unescape_line(line: &str) -> String {
+    let content = line.trim_start_matches([' ', '\t']);
+    let indent  = &line[..line.len()-content.len()];
+
+    if let Some(rest) = content.strip_prefix(',') {
+        let unescaped = rest.trim_start_matches(',');
+        if unescaped.starts_with('*') || unescaped.starts_with("#+") {
+            return format!("{}{}", indent, rest);
+        }
+    }
+    line.to_string()
+}
+
+/// Inverse of 'unescape_line', puts a comma in front of lines org would
+/// read as headline or keyword
+pub fn escape_line(line: &str) -> String {
+    let content = line.trim_start_matches([' ', '\t']);
+    let indent  = &line[..line.len()-content.len()];
+    let unescaped = content.trim_start_matches(',');
+
+    if unescaped.starts_with('*') || unescaped.starts_with("#+") {
+        return format!("{},{}", indent, content);
+    }
+    line.to_string()
+}
+
+/// Range of line indices making up the target of an include: the subtree
+/// of a headline given as '*Heading' or '#custom-id', or the element
+/// named 'name' along with its keywords. With 'only_contents', a
+/// subtree's headline and properties are left out.
+fn target_range(lines: &[String], target: &str,
+                only_contents: bool) -> Option<(usize, usize)> {
+    if let Some(heading) = target.strip_prefix('*') {
+        let start = lines.iter().position(|l|
+                headline_level(l).is_some() && headline_title(l) == heading.trim())?;
+        return Some(subtree_range(lines, start, only_contents));
+    }
+    if let Some(id) = target.strip_prefix('#') {
+        for (i, line) in lines.iter().enumerate() {
+            if headline_level(line).is_none() {
+                continue;
+            }
+            let (contents, _) = subtree_range(lines, i, true);
+            let drawer = lines[i+1..contents].iter()
+                                             .filter_map(|l| parse_node_property(l));
+            for (name, value) in drawer {
+                if name == "custom_id" && value == id {
+                    return Some(subtree_range(lines, i, only_contents));
+                }
+            }
+        }
+        return None;
+    }
+
+    let named = lines.iter().position(|l|
+            keyword(l, "#+NAME:").map(|n| n.trim()) == Some(target))?;
+    // keywords like '#+HEADER:' may precede or follow the name
+    let mut start = named;
+    while start > 0 && lines[start-1].trim_start().starts_with("#+") {
+        start -= 1;
+    }
+    let mut element = named + 1;
+    while element < lines.len() && lines[element].trim_start().starts_with("#+") &&
+          begin_block(&lines[element]).is_none() {
+        element += 1;
+    }
+    Some((start, element_end(lines, element)))
+}
+
+/// Index of the line after the element starting at 'start': a block ends
+/// with its '#+END_' line, a table with its last row or formula and a list
+/// or paragraph before the next blank line or other element
+fn element_end(lines: &[String], start: usize) -> usize {
+    let line = match lines.get(start) {
+        Some(l) => l,
+        None    => return start,
+    };
+    let after = |from: usize, belongs: fn(&str) -> bool| {
+        lines[from..].iter()
+                     .position(|l| !belongs(l))
+                     .map_or(lines.len(), |i| from + i)
+    };
+    if let Some((kind, _)) = begin_block(line) {
+        block_end(lines, start, &kind).map_or(start + 1, |end| end + 1)
+    } else if list_item(line).is_some() {
+        parse_list(lines, start, 0).1
+    } else if line.trim_start().starts_with('|') {
+        let rows = after(start, |l| l.trim_start().starts_with('|'));
+        after(rows, |l| keyword(l, "#+TBLFM:").is_some())
+    } else if is_text_line(line) {
+        after(start, is_text_line)
+    } else {
+        start + 1
+    }
+}
+
+/// Range of line indices of the subtree of the headline at 'start'
+fn subtree_range(lines: &[String], start: usize,
+                 only_contents: bool) -> (usize, usize) {
+    let level = headline_level(&lines[start]).unwrap_or(1);
+    let end = lines[start+1..].iter()
+                              .position(|l| headline_level(l).is_some_and(|n| n <= level))
+                              .map_or(lines.len(), |i| start + 1 + i);
+    if !only_contents {
+        return (start, end);
+    }
+    // skip the headline and its property drawer
+    let mut first = start + 1;
+    if first < end && lines[first].trim().eq_ignore_ascii_case(":PROPERTIES:") {
+        while first < end && !lines[first].trim().eq_ignore_ascii_case(":END:") {
+            first += 1;
+        }
+        first = (first + 1).min(end);
+    }
+    (first, end)
+}
+
+/// Changes the level of all headlines so that the topmost ones have the
+/// given level, as done by ':minlevel'
+fn shift_headlines(lines: &[String], level: usize) -> Vec<String> {
+    let top = match lines.iter().filter_map(|l| headline_level(l)).min() {
+        Some(t) => t,
+        None    => return lines.to_vec(),
+    };
+    lines.iter()
+         .map(|l| match headline_level(l) {
+             Some(n) => format!("{}{}", "*".repeat(n - top + level.max(1)), &l[n..]),
+             None    => l.clone(),
+         })
+         .collect()
+}
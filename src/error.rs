@@ -5,11 +5,24 @@ use std::fmt;
 pub enum ErrorKind {
     FileError{ msg: String },
     EmacsCallFailed,
+    EmacsFailed{ status: Option<i32>, msg: String },
     PandocCallFailed,
+    PandocFailed{ status: Option<i32>, msg: String },
     PdfLatexCallFailed,
+    LatexFailed{ file: String, line: Option<usize>, msg: String },
     CodeBlockNotFound,
     AmbiguousCodeBlockName,
     UnsatisfiableDependencies,
+    NowebReferenceNotFound{ name: String },
+    CyclicNowebReference{ name: String },
+    InvalidTangleMode{ mode: String },
+    LinkedBlockNotFound{ name: String },
+    CyclicInclude{ chain: Vec<String> },
+    IncludeTooDeep{ path: String, limit: usize },
+    IncludeTargetNotFound{ path: String, target: String },
+    PluginCallFailed{ name: String },
+    PluginFailed{ name: String, status: Option<i32> },
+    BackendNotFound{ format: String },
 }
 
 impl fmt::Display for ErrorKind {
@@ -17,11 +30,35 @@ impl fmt::Display for ErrorKind {
         match self {
             ErrorKind::FileError{msg}             => write!(f, "{}", msg),
             ErrorKind::EmacsCallFailed            => write!(f, "calling Emacs failed"),
+            ErrorKind::EmacsFailed{status, msg}   => write!(f, "Emacs {}:\n{}", exited(status), msg),
             ErrorKind::PandocCallFailed           => write!(f, "calling Pandoc failed"),
+            ErrorKind::PandocFailed{status, msg}  => write!(f, "Pandoc {}:\n{}", exited(status), msg),
             ErrorKind::PdfLatexCallFailed         => write!(f, "calling pdflatex failed"),
+            ErrorKind::LatexFailed{file, line, msg} => match line {
+                Some(line) => write!(f, "pdflatex failed at {}:{}: {}", file, line, msg),
+                None       => write!(f, "pdflatex failed on {}: {}", file, msg),
+            },
             ErrorKind::CodeBlockNotFound          => write!(f, "specified code block not found"),
             ErrorKind::AmbiguousCodeBlockName     => write!(f, "muliple code blocks match given name"),
             ErrorKind::UnsatisfiableDependencies  => write!(f, "dependencies can't be satisfied"),
+            ErrorKind::NowebReferenceNotFound{name} => write!(f, "no code block named '{}' for noweb reference", name),
+            ErrorKind::CyclicNowebReference{name}   => write!(f, "noweb reference to '{}' is cyclic", name),
+            ErrorKind::InvalidTangleMode{mode}      => write!(f, "invalid :tangle-mode '{}'", mode),
+            ErrorKind::LinkedBlockNotFound{name}    => write!(f, "linked code block '{}' not found", name),
+            ErrorKind::CyclicInclude{chain}         => write!(f, "cyclic #+INCLUDE: {}", chain.join(" -> ")),
+            ErrorKind::IncludeTooDeep{path, limit}  => write!(f, "including {} exceeds the maximum include depth of {}", path, limit),
+            ErrorKind::IncludeTargetNotFound{path, target} => write!(f, "'{}' not found in {}", target, path),
+            ErrorKind::PluginCallFailed{name}       => write!(f, "calling {} failed", name),
+            ErrorKind::PluginFailed{name, status}   => write!(f, "{} {}", name, exited(status)),
+            ErrorKind::BackendNotFound{format}      => write!(f, "no backend for the format '{}'", format),
         }
     }
 }
+
+/// Describes how a program ended, given its exit status
+fn exited(status: &Option<i32>) -> String {
+    match status {
+        Some(code) => format!("exited with status {}", code),
+        None       => "was terminated by a signal".to_string(),
+    }
+}
@@ -1,14 +1,38 @@
 
 use std::process::Command;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
 
 use error::ErrorKind;
-use file::{read_file, write_file};
+use header::{HeaderArgs, parse_file_mode};
+use document::{self, Document, Element, List, Paragraph, Scopes, escape_line};
+use backend::{Backend, Plugin, Registry};
+use mapping::rewrite_locations;
+use diff::unified_diff;
+use json;
+use latex::to_latex;
+use html::to_html;
+use markdown::{to_markdown, Flavor};
+use file::{read_file, read_lines, write_file, write_file_atomic, expand_tabs,
+           LineEnding, create_parent_dirs, set_mode, resolve_path,
+           relative_path, reroot_path, parent_dir};
 
 #[derive(Copy, Clone, Debug)]
 enum PdfOpt {
     Emacs,
     EmacsMinted,
     Pandoc,
+    // LaTeX written by exorg itself
+    Native,
+}
+
+/// Handling of '<<name>>' references inside a block, set via ':noweb'
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Noweb {
+    No,
+    Expand,
+    Strip,
 }
 
 #[derive(Clone, Debug)]
@@ -18,265 +42,778 @@ struct SrcBlock {
     pub lines: Vec<String>,
     pub dependencies: Vec<String>,
     pub filename: Option<String>,
+    // effective header arguments, including inherited ones
+    pub args: HeaderArgs,
+    // line ending of the file the block was read from
+    pub line_ending: LineEnding,
+    pub anchor: Anchor,
+    // file the lines were read from and line number of the first one
+    pub path: String,
+    pub line: usize,
+    // indentation removed from each line
+    pub indent: String,
+}
+
+/// File and line number a tangled line was taken from
+#[derive(Clone, Debug, PartialEq)]
+struct Origin {
+    pub path: String,
+    pub line: usize,
+    // added to a column of the tangled line to get the column in the source
+    pub column: isize,
+}
+
+// lines produced by tangling along with their origins
+type Tangled = Vec<(String, Option<Origin>)>;
+
+/// Position of a block within the org document, used for comments linking
+/// back to it
+#[derive(Clone, Debug)]
+struct Anchor {
+    pub org_path: String,
+    // title of the enclosing headline
+    pub heading:  String,
+    // number of the block below its headline, starting at 1
+    pub index:    usize,
+    // org text in front of the block
+    pub org_text: Vec<String>,
+}
+
+impl SrcBlock {
+    fn noweb(&self) -> Noweb {
+        match self.args.get("noweb") {
+            Some("yes") | Some("tangle") |
+            Some("no-export") | Some("strip-export") => Noweb::Expand,
+            Some("strip-tangle")                     => Noweb::Strip,
+            _                                        => Noweb::No,
+        }
+    }
+
+    /// Lines of the block along with their origins
+    fn tangled_lines(&self) -> Tangled {
+        self.lines.iter()
+                  .enumerate()
+                  .map(|(i, l)| (l.clone(), Some(Origin {
+                                    path:   self.path.clone(),
+                                    line:   self.line + i,
+                                    column: self.indent.chars().count() as isize,
+                                })))
+                  .collect()
+    }
+
+    /// Name identifying the block in comments: its name or the headline
+    /// followed by the number of the block below it
+    fn source_name(&self) -> String {
+        if !self.name.is_empty() {
+            return self.name.clone();
+        }
+        let heading = if self.anchor.heading.is_empty() {
+            "No heading"
+        } else {
+            &self.anchor.heading
+        };
+        format!("{}:{}", heading, self.anchor.index)
+    }
+
+    /// Org link to the block, as used by org-babel in tangle comments. The
+    /// path is relative to the directory of the tangled file 'out_file'.
+    fn link(&self, out_file: &str) -> String {
+        let target = if !self.name.is_empty() {
+            format!("::{}", self.name)
+        } else if !self.anchor.heading.is_empty() {
+            format!("::*{}", self.anchor.heading)
+        } else {
+            String::new()
+        };
+        format!("[[file:{}{}][{}]]",
+                relative_path(&parent_dir(out_file), &self.anchor.org_path),
+                target, self.source_name())
+    }
 }
 
 #[derive(Clone)]
 struct FileContent {
     pub name:  String,
     pub lines: Vec<String>,
+    pub line_ending: LineEnding,
+    // create missing parent directories, set via ':mkdirp'
+    pub mkdirp: bool,
+    // first line of the file, set via ':shebang'
+    pub shebang: Option<String>,
+    // Unix permissions, set via ':tangle-mode'
+    pub mode: Option<u32>,
+    // origin of each line, empty if not applicable
+    pub origins: Vec<Option<Origin>>,
+    // blocks the file was assembled from:
+    // (<block name>, <source file>, <first line>, <number of lines>), the
+    // lines being those of the code without the comments of ':comments'
+    pub blocks: Vec<(String, String, usize, usize)>,
+}
+
+/// Options given on the command line
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    // name of the block to extract
+    pub block:        Option<String>,
+    pub out_filename: Option<String>,
+    // write a '.map.json' file next to each tangled file
+    pub source_map:   bool,
+    // only report what would be written
+    pub dry_run:      bool,
+    // path of a JSON file recording the blocks of each tangled file
+    pub manifest:     Option<String>,
+    // directory replacing the org file's one as root of all tangled files
+    pub output_dir:   Option<String>,
+    // export with a backend even if the format is also a language
+    pub backend:      bool,
+}
+
+impl Options {
+    /// The options as given on the command line, e.g. for external backends
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(block) = &self.block {
+            args.push("-b".to_string());
+            args.push(block.clone());
+        }
+        if let Some(out_filename) = &self.out_filename {
+            args.push("-o".to_string());
+            args.push(out_filename.clone());
+        }
+        if self.source_map {
+            args.push("--source-map".to_string());
+        }
+        if self.dry_run {
+            args.push("--dry-run".to_string());
+        }
+        if let Some(manifest) = &self.manifest {
+            args.push("--manifest".to_string());
+            args.push(manifest.clone());
+        }
+        if let Some(output_dir) = &self.output_dir {
+            args.push("--output-dir".to_string());
+            args.push(output_dir.clone());
+        }
+        if self.backend {
+            args.push("--backend".to_string());
+        }
+        args
+    }
 }
 
 impl FileContent {
-    fn new(name: &String) -> Self {
+    fn new(name: &str) -> Self {
         FileContent {
-            name: name.clone(),
-            lines: Vec::new()
+            name: name.to_string(),
+            lines: Vec::new(),
+            line_ending: LineEnding::Lf,
+            mkdirp: false,
+            shebang: None,
+            mode: None,
+            origins: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Appends the tangled lines of a block followed by an empty line. The
+    /// first block added determines the line ending of the file, the first
+    /// block specifying ':shebang' or ':tangle-mode' their values.
+    fn append_block(&mut self, block: &SrcBlock,
+                    lines: Tangled) -> Result<(), ErrorKind> {
+        if self.lines.is_empty() {
+            self.line_ending = block.line_ending;
         }
+        if block.args.get("mkdirp") == Some("yes") {
+            self.mkdirp = true;
+        }
+        if self.shebang.is_none() {
+            self.shebang = block.args.get("shebang")
+                                     .filter(|s| !s.is_empty())
+                                     .map(|s| s.to_string());
+        }
+        if self.mode.is_none() {
+            if let Some(mode) = block.args.get("tangle-mode") {
+                self.mode = Some(parse_file_mode(mode).ok_or_else(||
+                                ErrorKind::InvalidTangleMode { mode: mode.to_string() })?);
+            }
+        }
+        // comments added around the code don't come from any org line
+        let code = lines.iter().filter(|(_, origin)| origin.is_some()).count();
+        self.blocks.push((block.source_name(), block.path.clone(), block.line, code));
+        for (line, origin) in lines {
+            self.lines.push(line);
+            self.origins.push(origin);
+        }
+        self.lines.push(String::new());
+        self.origins.push(None);
+        Ok(())
+    }
+
+    /// All lines of the file, including the shebang
+    fn content(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(shebang) = &self.shebang {
+            lines.push(shebang.clone());
+        }
+        lines.extend(self.lines.iter().cloned());
+        lines
+    }
+
+    /// The file's content as written to disk
+    fn render(&self) -> String {
+        self.content()
+            .iter()
+            .map(|l| format!("{}{}", l, self.line_ending.as_str()))
+            .collect()
+    }
+
+    /// Origins of the lines returned by 'content'
+    fn content_origins(&self) -> Vec<Option<Origin>> {
+        let mut origins = Vec::new();
+        if self.shebang.is_some() {
+            origins.push(None);
+        }
+        origins.extend(self.origins.iter().cloned());
+        origins
+    }
+
+    /// Whether the file on disk doesn't exist or differs from the content
+    fn changed(&self) -> bool {
+        fs::read(&self.name).ok().as_deref() != Some(self.render().as_bytes())
     }
 
     /// Writes its lines into the file at the path stored in 'name' if 'lines'
-    /// is not empty
+    /// is not empty and differ from the file's current content. Returns
+    /// whether the file was written.
     fn write_content(&self) -> Result<bool, ErrorKind> {
-        if self.lines.len() > 0 {
-            write_file(&self.name, &self.lines)?;
-            return Ok(true);
+        if self.lines.is_empty() {
+            return Ok(false);
+        }
+        if self.mkdirp {
+            create_parent_dirs(&self.name)?;
+        }
+        let written = write_file_atomic(&self.name, &self.render())?;
+
+        // like org, make scripts with a shebang executable when writing
+        // them, unless ':tangle-mode' says otherwise
+        let mode = match (self.mode, &self.shebang) {
+            (Some(m), _)               => Some(m),
+            (None, Some(_)) if written => Some(0o755),
+            (None, _)                  => None,
+        };
+        if let Some(m) = mode {
+            set_mode(&self.name, m)?;
         }
-        Ok(false)
+        Ok(written)
+    }
+
+    /// Writes a JSON file '<name>.map.json' describing which ranges of lines
+    /// were taken from which lines of the org or included files
+    fn write_source_map(&self) -> Result<(), ErrorKind> {
+        // (<first line>, <last line>, <source>, <first source line>)
+        let mut ranges: Vec<(usize, usize, String, usize)> = Vec::new();
+        for (i, origin) in self.content_origins().into_iter().enumerate() {
+            let origin = match origin {
+                Some(o) => o,
+                None    => continue,
+            };
+            if let Some(last) = ranges.last_mut() {
+                if last.1 == i && last.2 == origin.path &&
+                   last.3 + (last.1 - last.0) + 1 == origin.line {
+                    last.1 += 1;
+                    continue;
+                }
+            }
+            ranges.push((i+1, i+1, origin.path, origin.line));
+        }
+
+        // sources are given relative to the map file like the tangled file
+        let dir = parent_dir(&self.name);
+        let mut lines = vec![
+            "{".to_string(),
+            format!(" \"file\": \"{}\",", json::escape(
+                        &relative_path(&dir, &self.name))),
+            " \"mappings\": [".to_string(),
+        ];
+        for (i, (first, last, source, source_first)) in ranges.iter().enumerate() {
+            let comma = if i < ranges.len()-1 { "," } else { "" };
+            lines.push(format!("  {{\"lines\": [{}, {}], \"source\": \"{}\", \"source_lines\": [{}, {}]}}{}",
+                               first, last,
+                               json::escape(&relative_path(&dir, source)),
+                               source_first, source_first + (last - first), comma));
+        }
+        lines.push(" ]".to_string());
+        lines.push("}".to_string());
+
+        let content: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+        write_file_atomic(&format!("{}.map.json", self.name), &content)?;
+        Ok(())
     }
 }
 
+// langs: (<language name>, <file prefix>)
+type Langs = Vec<(String, String)>;
+
 #[derive(Clone, Debug)]
 pub struct Exporter {
     input_path:     String,
-    content_lines:  Vec<String>,
+    document:       Document,
     src_blocks:     Vec<SrcBlock>,
-    // langs: (<language name>, <file prefix>)
-    langs:          Vec<(String, String)>,
+    langs:          Langs,
 }
 
-impl Exporter {
-    pub fn from_file(filename: &String) -> Result<Self, ErrorKind> {
-        let lines        = read_file(filename)?;
-        let (src, langs) = Exporter::extract_src(&lines)?;
-        Ok(Exporter {
-            input_path:     filename.to_owned(),
-            content_lines:  lines,
-            src_blocks:     src,
-            langs:          langs,
-        })
+/// PDF export via Emacs or Pandoc
+struct Pdf {
+    name: &'static str,
+    opt:  PdfOpt,
+}
+
+impl Backend for Pdf {
+    fn name(&self) -> &str {
+        self.name
     }
 
-    fn src_blocks(&self) -> &Vec<SrcBlock> {
-        &self.src_blocks
+    fn suffix(&self) -> &str {
+        "pdf"
     }
 
-    fn langs(&self) -> &Vec<(String, String)> {
-        &self.langs
+    fn export(&self, document: &Document, _options: &Options) -> Result<(), ErrorKind> {
+        Exporter::from_document(document).weave(self.opt)
     }
+}
 
-    pub fn export(&self, format: &String, block: &Option<String>,
-                        out_filename: &Option<String>) -> Result<(), ErrorKind> {
+/// Standalone HTML page
+struct Html;
+
+impl Backend for Html {
+    fn name(&self) -> &str {
+        "html"
+    }
 
+    fn suffix(&self) -> &str {
+        "html"
+    }
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind> {
+        let path = backend_output_path(self, document, options);
+        write_file(&path, &to_html(document), LineEnding::Lf)
+    }
+}
+
+/// Markdown document
+struct Markdown {
+    name:   &'static str,
+    flavor: Flavor,
+}
+
+impl Backend for Markdown {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn suffix(&self) -> &str {
+        "md"
+    }
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind> {
+        let path = backend_output_path(self, document, options);
+        write_file(&path, &to_markdown(document, self.flavor), LineEnding::Lf)
+    }
+}
+
+/// File a backend writes its output to: the one given by '-o', or else the
+/// org file's name with the backend's suffix
+fn backend_output_path(backend: &dyn Backend, document: &Document,
+                       options: &Options) -> String {
+    match &options.out_filename {
+        Some(f) => f.clone(),
+        None    => format!("{}.{}", Exporter::from_document(document).output_file_name(""),
+                           backend.suffix()),
+    }
+}
+
+/// Python blocks as Jupyter notebook
+struct Jupyter;
+
+impl Backend for Jupyter {
+    fn name(&self) -> &str {
+        "jupyter"
+    }
+
+    fn suffix(&self) -> &str {
+        "ipynb"
+    }
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind> {
+        Exporter::from_document(document).tangle("jupyter", options)
+    }
+}
+
+/// All blocks to the files given by ':tangle'
+struct Tangle;
+
+impl Backend for Tangle {
+    fn name(&self) -> &str {
+        "."
+    }
+
+    fn suffix(&self) -> &str {
+        ""
+    }
+
+    fn export(&self, document: &Document, options: &Options) -> Result<(), ErrorKind> {
+        Exporter::from_document(document).tangle(".", options)
+    }
+}
+
+/// Emacs Lisp string literal
+fn elisp_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Backends available without registering them
+pub fn builtin_backends() -> Vec<Box<dyn Backend>> {
+    vec![
+        Box::new(Pdf { name: "pdf",        opt: PdfOpt::Emacs }),
+        Box::new(Pdf { name: "pdf-minted", opt: PdfOpt::EmacsMinted }),
+        Box::new(Pdf { name: "pdf-pandoc", opt: PdfOpt::Pandoc }),
+        Box::new(Pdf { name: "pdf-native", opt: PdfOpt::Native }),
+        Box::new(Html),
+        Box::new(Markdown { name: "md",  flavor: Flavor::Markdown }),
+        Box::new(Markdown { name: "gfm", flavor: Flavor::Gfm }),
+        Box::new(Jupyter),
+        Box::new(Tangle),
+    ]
+}
+
+impl Exporter {
+    pub fn from_file(filename: &str) -> Result<Self, ErrorKind> {
+        Ok(Exporter::from_document(&Document::from_file(filename)?))
+    }
+
+    pub fn from_document(document: &Document) -> Self {
+        let mut src_blocks = Vec::new();
+        let mut langs      = Vec::new();
+        Exporter::collect_blocks(document, &Scopes::default(), &mut src_blocks, &mut langs);
+        Exporter {
+            input_path: document.path.clone(),
+            document:   document.clone(),
+            src_blocks,
+            langs,
+        }
+    }
+
+    /// Exports to the given format using the built-in backends
+    pub fn export(&self, format: &str, options: &Options) -> Result<(), ErrorKind> {
+        self.export_with(&Registry::new(), format, options)
+    }
+
+    /// Exports to the given format using the backends of 'registry'. Formats
+    /// defined by '#+SRC_LANG:' or without backend are taken as language whose
+    /// blocks are extracted, unless 'options.backend' asks for a backend.
+    pub fn export_with(&self, registry: &Registry, format: &str,
+                       options: &Options) -> Result<(), ErrorKind> {
         let lower_format = format.to_lowercase();
-        if lower_format == "pdf" || lower_format.starts_with("pdf-") {
-            match lower_format.as_str() {
-                "pdf"        => self.weave(PdfOpt::Emacs)?,
-                "pdf-minted" => self.weave(PdfOpt::EmacsMinted)?,
-                "pdf-pandoc" => self.weave(PdfOpt::Pandoc)?,
-                _ => unreachable!(),
-            }
-        } else {
-            self.tangle(&lower_format, block, out_filename)?;
+        // '#+SRC_LANG:' claims a format for extracting, e.g. to get the html
+        // blocks of a document with 'exorg html'
+        let claimed = self.langs.iter().any(|(lang, _)| *lang == lower_format);
+        if claimed && !options.backend {
+            return self.tangle(&lower_format, options);
+        }
+        if let Some(backend) = registry.get(&lower_format) {
+            return backend.export(&self.document, options);
+        }
+        // languages of the document take precedence over external backends
+        let is_lang = self.src_blocks.iter().any(|b| b.lang == lower_format);
+        if is_lang && !options.backend {
+            return self.tangle(&lower_format, options);
+        }
+        match Plugin::find(&lower_format) {
+            Some(plugin)            => plugin.export(&self.document, options),
+            None if options.backend => Err(ErrorKind::BackendNotFound { format: lower_format }),
+            None                    => self.tangle(&lower_format, options),
         }
-        Ok(())
     }
 
-    fn extract_src(lines: &Vec<String>) -> Result<(Vec<SrcBlock>, Vec<(String, String)>), ErrorKind> {
-        let mut lang_name   = None;
-        let mut block_name  = None;
-        let mut block_file  = None;
-        let mut block_lines = Vec::new();
-        let mut block_deps  = Vec::new();
-        let mut langs       = Vec::new();
-        let mut src_blocks  = Vec::new();
-
-        let mut src = false;
-        for full_line in lines {
-            let line = full_line.replace("\n", "");
-
-            if line.starts_with("#+BEGIN_SRC") {
-                let tup = Exporter::parse_begin_src(&line);
-                lang_name  = tup.0;
-                block_file = tup.1;
-                src = true;
-            } else if line.starts_with("#+END_SRC") {
-                src_blocks.push(SrcBlock {
-                    name:  block_name.unwrap_or("".to_string()),
-                    lang:  lang_name.unwrap_or("".to_string()).to_string(),
-                    lines: block_lines.clone(),
-                    dependencies: block_deps.clone(),
-                    filename: block_file.clone(),
-                });
-                block_lines.clear();
-                block_deps.clear();
-                block_name = None;
-                block_file = None;
-                lang_name  = None;
-                src = false;
-            } else if line.starts_with("#+NAME:") {
-                block_name = Some(Exporter::parse_name(&line));
-            } else if line.starts_with("#+DEPS:") {
-                block_deps = Exporter::parse_deps(&line);
-            } else if line.starts_with("#+SRC_LANG:") {
-                langs.push(Exporter::parse_src_lang(&line));
-            } else if line.starts_with("#+INCLUDE:") {
-                Exporter::parse_include(&line, &mut src_blocks,
-                                        &mut langs, block_name, block_deps)?;
-                block_name = None;
-                block_file = None;
-                block_deps = Vec::new();
-            } else if src {
-                block_lines.push(line.to_owned());
-            }
-        }
-        Ok((src_blocks, langs))
-    }
-
-    fn parse_begin_src(line: &String) -> (Option<String>, Option<String>) {
-        let metadata = line.split(" ")
-                            // skip the "#+BEGIN_SRC" phrase
-                            .skip(1)
-                            // discard empty strings which occur if
-                            // multiple spaces are inbetween args
-                            .filter(|n| n.len() > 0)
-                            // flags like -i and -n not relevant here
-                            .filter(|n| !(n.starts_with("-") &&
-                                          n.len() == 2));
-
-        let remaining: Vec<String> = metadata.map(|s| s.to_string()).collect();
-        let lang_str: Option<String> = if remaining.len() > 0 {
-            Some(remaining[0].clone())
-        } else { None };
-        let mut filename: Option<String> = None;
-
-        for i in 0..remaining.len() {
-            if remaining[i] == ":tangle" && remaining.len() > i+1 {
-                match remaining[i+1].as_str() {
-                    "no"  => {},
-                    "yes" => {},
-                    name  => filename = Some(name.to_string()),
-                }
-                break;
+    /// Collects the blocks of a document and the languages defined by
+    /// '#+SRC_LANG:'. Its headlines are nested in the 'outer' ones, e.g. of
+    /// the file including it.
+    fn collect_blocks(document: &Document, outer: &Scopes,
+                      src_blocks: &mut Vec<SrcBlock>, langs: &mut Langs) {
+        let mut scopes = Scopes::enter_document(outer, document);
+
+        let mut anchor = Anchor {
+            org_path: document.path.clone(),
+            heading:  String::new(),
+            index:    0,
+            org_text: Vec::new(),
+        };
+        let mut deps = Vec::new();
+        // last line of the text collected for 'anchor'
+        let mut text_end = 0;
+
+        for element in &document.elements {
+            match element {
+                Element::Headline(h) => {
+                    scopes.enter_headline(h);
+                    anchor.heading = h.title.clone();
+                    anchor.index   = 0;
+                    anchor.org_text.clear();
+                },
+                Element::Src(block) => {
+                    anchor.index += 1;
+                    src_blocks.push(Exporter::tangle_block(block, document, &scopes,
+                                                           &anchor, deps.split_off(0)));
+                    anchor.org_text.clear();
+                },
+                Element::Keyword(k) if k.key == "DEPS" => {
+                    deps = Exporter::parse_deps(&k.value);
+                },
+                Element::Keyword(k) if k.key == "SRC_LANG" => {
+                    langs.push(Exporter::parse_src_lang(&k.value));
+                },
+                Element::Include(include) => {
+                    anchor.index += 1;
+                    match &include.content {
+                        // blocks of org files are nested in the current
+                        // headline, included source files form a block
+                        // belonging to it
+                        Some(content) if include.kind.is_empty() => {
+                            Exporter::collect_blocks(content, &scopes, src_blocks, langs);
+                        },
+                        Some(content) => {
+                            for block in content.src_blocks() {
+                                src_blocks.push(Exporter::tangle_block(block, content,
+                                                        &scopes, &anchor, deps.clone()));
+                            }
+                        },
+                        None => {},
+                    }
+                    anchor.org_text.clear();
+                    deps.clear();
+                },
+                Element::Paragraph(Paragraph { lines, span }) |
+                Element::List(List { lines, span, .. }) => {
+                    // text separated by other lines
+                    if !anchor.org_text.is_empty() && text_end + 1 < span.start {
+                        anchor.org_text.push(String::new());
+                    }
+                    anchor.org_text.extend(lines.iter().cloned());
+                    text_end = span.end;
+                },
+                _ => {},
             }
         }
+    }
 
-        (lang_str, filename)
+    /// Block to tangle for a source block of the given document
+    fn tangle_block(block: &document::SrcBlock, document: &Document,
+                    scopes: &Scopes, anchor: &Anchor, dependencies: Vec<String>) -> SrcBlock {
+        let args = block.header_args(scopes);
+        SrcBlock {
+            name:  block.name.clone().unwrap_or_default(),
+            lang:  block.lang.clone(),
+            lines: block.lines.clone(),
+            dependencies,
+            // ':tangle' paths are relative to the org file
+            filename: Exporter::tangle_file(&args)
+                              .map(|f| resolve_path(&anchor.org_path, &f)),
+            args,
+            line_ending: document.line_ending,
+            anchor: anchor.clone(),
+            path: document.path.clone(),
+            line: block.line,
+            indent: block.indent.clone(),
+        }
     }
 
-    fn parse_name(line: &String) -> String {
-        let trimmed = line.replace("#+NAME:", "");
-        trimmed.trim().to_string()
+    /// The explicit file name given by ':tangle', if any
+    fn tangle_file(args: &HeaderArgs) -> Option<String> {
+        match args.get("tangle") {
+            None | Some("no") | Some("yes") => None,
+            Some(name)                      => Some(name.to_string()),
+        }
     }
 
-    fn parse_deps(line: &String) -> Vec<String> {
-        let mut trimmed = line.replace("#+DEPS:", "");
-        trimmed = trimmed.trim().to_string();
-                
-        trimmed.split(" ")
-                .filter(|n| n.len() > 0)
+    fn parse_deps(args: &str) -> Vec<String> {
+        args.split(' ')
+                .filter(|n| !n.is_empty())
                 .map(|n| n.to_string())
                 .collect()
     }
 
-    fn parse_src_lang(line: &String) -> (String, String) {
-        let mut trimmed = line.replace("#+SRC_LANG:", "");
-        trimmed = trimmed.trim().to_string();
-
-        let mut args = trimmed.split(" ")
-                        .filter(|n| n.len() > 0);
-        let lang:   String = args.nth(0).unwrap_or("fail").to_string();
-        let suffix: String = args.nth(1).unwrap_or("fail").to_string();
+    fn parse_src_lang(args: &str) -> (String, String) {
+        let mut args = args.split(' ')
+                        .filter(|n| !n.is_empty());
+        let lang:   String = args.next().unwrap_or("fail").to_string();
+        let suffix: String = args.next().unwrap_or("fail").to_string();
         (lang, suffix)
     }
 
-    fn parse_include(line: &String, src_blocks: &mut Vec<SrcBlock>,
-                     langs: &mut Vec<(String, String)>,
-                     block_name: Option<String>, block_deps: Vec<String>) -> Result<(), ErrorKind> {
-        let args = line.split(" ")
-                    .filter(|n| n.len() > 0)
-                    .map(|n| n.to_string())
-                    .collect::<Vec<String>>();
-        let len = args.len();
-        
-        if len == 2 { // no optional arguments => .org file
-            let included_filename = &args[1];
-            let exporter = Exporter::from_file(included_filename)?;
-            let mut new_src_blocks = exporter.src_blocks().clone();
-            let mut new_langs      = exporter.langs().clone();
-            src_blocks.append(&mut new_src_blocks);
-            langs.append(&mut new_langs);
-        } else if len >= 4 &&
-                  &args[2] == "src" { // src import
-            let included_filename = &args[1];
-            let lang  = args[3].clone();
-            let lines = read_file(included_filename)?;
-
-            let block_file = if args.len() >= 6 && args[4] == ":tangle" {
-                Some(args[5].clone())
-            } else { None };
-
-
-            let name  = match block_name {
-                Some(n) => n,
-                None    => String::new(),
+    /// Detangling: writes the content of the blocks enclosed in link
+    /// comments in a tangled file back into the org files or the source files
+    /// they include
+    pub fn detangle(path: &str) -> Result<(), ErrorKind> {
+        let lines = read_file(path)?;
+        let mut exporters: Vec<Exporter> = Vec::new();
+        // (<file>, <index of first line>, <number of lines>, <new lines>)
+        let mut edits: Vec<(String, usize, usize, Vec<String>)> = Vec::new();
+
+        for (link_path, name, body) in Exporter::linked_regions(&lines) {
+            // links are relative to the tangled file
+            let org_path = resolve_path(path, &link_path);
+            let normalized = Exporter::normalize_path(&org_path);
+            let ei = match exporters.iter().position(|e| e.input_path == org_path) {
+                Some(i) => i,
+                None    => {
+                    exporters.push(Exporter::from_file(&org_path)?);
+                    exporters.len()-1
+                },
+            };
+            let blocks: Vec<&SrcBlock> = exporters[ei].src_blocks.iter()
+                    .filter(|b| Exporter::normalize_path(&b.anchor.org_path) == normalized &&
+                                b.source_name() == name)
+                    .collect();
+            let block = match blocks.len() {
+                0 => return Err(ErrorKind::LinkedBlockNotFound { name }),
+                1 => blocks[0],
+                _ => return Err(ErrorKind::AmbiguousCodeBlockName),
             };
 
-            src_blocks.push(SrcBlock {
-                name:  name,
-                lang:  lang,
-                lines: lines,
-                dependencies: block_deps,
-                filename: block_file,
-            });
+            if block.lines == body {
+                continue;
+            }
+            // ':noweb' may be inherited from a file including this one, which
+            // isn't known here, so any reference might have been expanded
+            if block.lines.iter().any(|l| Exporter::parse_noweb_ref(l).is_some()) {
+                println!("Warning: {} not detangled as it contains noweb references",
+                         name);
+                continue;
+            }
+
+            // lines inside org files get their indentation and escaping back
+            let new_lines = if block.path == block.anchor.org_path {
+                body.iter()
+                    .map(|l| if l.trim().is_empty() {
+                        String::new()
+                    } else {
+                        format!("{}{}", block.indent, escape_line(l))
+                    })
+                    .collect()
+            } else {
+                body
+            };
+            edits.push((block.path.clone(), block.line-1, block.lines.len(), new_lines));
+        }
+
+        // apply the edits of each file from the bottom up so that the line
+        // numbers of the remaining ones stay valid
+        edits.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        let mut files: Vec<String> = edits.iter().map(|e| e.0.clone()).collect();
+        files.dedup();
+        for file in files {
+            let (mut content, ending) = read_lines(&file)?;
+            let mut count = 0;
+            for (_, start, len, new_lines) in edits.iter().filter(|e| e.0 == file) {
+                content.splice(*start..start+len, new_lines.iter().cloned());
+                count += 1;
+            }
+            let text: String = content.iter()
+                                      .map(|l| format!("{}{}", l, ending.as_str()))
+                                      .collect();
+            write_file_atomic(&file, &text)?;
+            println!("{}: {} block(s) updated", file, count);
         }
-        // other variants of includes are assumed to contain no src code
         Ok(())
     }
 
+    /// Finds the regions of a tangled file enclosed in link comments and
+    /// returns org file, block name and lines of each. Regions nested in
+    /// others, i.e. marked noweb references, are part of the outer one.
+    fn linked_regions(lines: &[String]) -> Vec<(String, String, Vec<String>)> {
+        let mut regions = Vec::new();
+        // (<org file>, <block name>, <index of first line>)
+        let mut open: Vec<(String, String, usize)> = Vec::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            if let Some((path, name)) = Exporter::parse_link_comment(line) {
+                open.push((path, name, i+1));
+                continue;
+            }
+            let closed = match open.last() {
+                Some(last) => line.contains(&format!("{} ends here", last.1)),
+                None       => false,
+            };
+            if closed {
+                let (path, name, start) = open.pop().unwrap();
+                if open.is_empty() {
+                    regions.push((path, name, lines[start..i].to_vec()));
+                }
+            }
+        }
+        regions
+    }
+
+    /// Extracts org file and block name from a line containing a link
+    /// comment like '# [[file:notes.org::*Setup][Setup:1]]'
+    fn parse_link_comment(line: &str) -> Option<(String, String)> {
+        let start = line.find("[[file:")? + 7;
+        let mid   = start + line[start..].find("][")?;
+        let end   = mid + 2 + line[mid+2..].find("]]")?;
+
+        let target = &line[start..mid];
+        let path   = match target.find("::") {
+            Some(i) => &target[..i],
+            None    => target,
+        };
+        Some((path.to_string(), line[mid+2..end].to_string()))
+    }
+
     /// PDF/LaTeX
     fn weave(&self, pdf_opt: PdfOpt) -> Result<(), ErrorKind> {
-        let tex_file_path = self.output_file_name(&"latex".to_string());
+        let tex_file_path = self.output_file_name("latex");
 
         match pdf_opt {
             PdfOpt::Emacs => {
-                self.call_emacs()?;
+                self.call_emacs("")?;
                 self.call_latex(&tex_file_path)?;
             },
             PdfOpt::EmacsMinted => {
-                self.call_emacs()?;
-                // open .tex file and substitute verbatim blocks with minted src blocks,
-                // then compile to pdf
-                let lines = self.mint_tex( &read_file(&tex_file_path)? );
-                write_file( &tex_file_path, &lines )?;
+                self.call_emacs(&self.minted_setup())?;
+                // tabs are expanded as minted would otherwise render them
+                // inconsistently
+                let tex = read_file(&tex_file_path)?.iter()
+                                                    .map(|l| expand_tabs(l, 4))
+                                                    .collect::<Vec<String>>();
+                write_file(&tex_file_path, &tex, LineEnding::Lf)?;
                 self.call_latex(&tex_file_path)?;
             },
             PdfOpt::Pandoc => self.call_pandoc()?,
+            PdfOpt::Native => {
+                write_file(&tex_file_path, &to_latex(&self.document), LineEnding::Lf)?;
+                self.call_latex(&tex_file_path)?;
+            },
         }
 
         Ok(())
     }
 
-    fn call_emacs(&self) -> Result<(), ErrorKind> {
-        let full_cmd = "(progn (setq org-confirm-babel-evaluate nil) (org-latex-export-to-latex) (kill-emacs))";
+    /// Exports the org file to LaTeX after evaluating the Emacs Lisp 'setup'
+    fn call_emacs(&self, setup: &str) -> Result<(), ErrorKind> {
+        let full_cmd = format!("(progn (setq org-confirm-babel-evaluate nil) {} \
+                                (org-latex-export-to-latex) (kill-emacs))", setup);
 
         match Command::new("emacs")
                     .arg(&self.input_path)
                     .arg("--batch")
                     .arg("--eval")
-                    .arg(full_cmd)
+                    .arg(&full_cmd)
                     .output() {
-            Err(_) => return Err(ErrorKind::EmacsCallFailed),
-            Ok(_)  => return Ok(()),
+            Err(_) => Err(ErrorKind::EmacsCallFailed),
+            Ok(m) if !m.status.success() => Err(ErrorKind::EmacsFailed {
+                                status: m.status.code(),
+                                msg: Exporter::output_excerpt(&m.stderr) }),
+            Ok(_)  => Ok(()),
         }
     }
 
@@ -300,37 +837,234 @@ impl Exporter {
                     .arg("--pdf-engine-opt=-shell-escape")
                     .arg("--toc")
                     .output() {
-            Err(_) => return Err(ErrorKind::PandocCallFailed),
-            Ok(_)  => return Ok(()),
+            Err(_) => Err(ErrorKind::PandocCallFailed),
+            Ok(m) if !m.status.success() => Err(ErrorKind::PandocFailed {
+                                status: m.status.code(),
+                                msg: Exporter::output_excerpt(&m.stderr) }),
+            Ok(_)  => Ok(()),
         }
     }
 
-    fn call_latex(&self, path: &String) -> Result<(), ErrorKind> {
-        match Command::new("pdflatex")
-                    .arg("-shell-escape")
-                    .arg(path)
-                    .output() {
-            Err(_) => return Err(ErrorKind::PdfLatexCallFailed),
-            Ok(m)  => {
-                // if no PDF was produced due to a fatal error, print the
-                // error message
-                let out = format!("{}", m.stdout.iter()
-                                                    .map(|n| *n as char)
-                                                    .collect::<String>());
-                if out.contains("no output PDF file produced") {
-                    println!("ERROR occurred. Log:\n{}", out);
-                }
-                return Ok(());
+    /// Runs pdflatex in the directory of the .tex file, so the PDF ends up
+    /// next to it
+    fn call_latex(&self, path: &str) -> Result<(), ErrorKind> {
+        let dir = parent_dir(path);
+        let file_name = Path::new(path).file_name().unwrap_or_default();
+        let mut command = Command::new("pdflatex");
+        if !dir.is_empty() {
+            command.current_dir(&dir);
+        }
+        // without a terminal to ask, pdflatex stops at the first error
+        match command.arg("-shell-escape")
+                     .arg("-interaction=nonstopmode")
+                     .arg("-halt-on-error")
+                     .arg(file_name)
+                     .output() {
+            Err(_) => Err(ErrorKind::PdfLatexCallFailed),
+            Ok(m) if !m.status.success() => {
+                let out = String::from_utf8_lossy(&m.stdout);
+                let (line, msg) = match Exporter::latex_error(&out) {
+                    Some(error) => error,
+                    None        => (None, Exporter::output_excerpt(&m.stdout)),
+                };
+                Err(ErrorKind::LatexFailed { file: path.to_string(), line, msg })
             },
+            Ok(_)  => Ok(()),
+        }
+    }
+
+    /// First error in the output of pdflatex with the line of the .tex file
+    /// it occurred in, e.g.
+    ///   ! Undefined control sequence.
+    ///   l.12 \foo
+    fn latex_error(output: &str) -> Option<(Option<usize>, String)> {
+        let lines: Vec<&str> = output.lines().collect();
+        let start = lines.iter().position(|l| l.starts_with("! "))?;
+        let mut msg = lines[start][2..].to_string();
+        for line in &lines[start+1..] {
+            if let Some(rest) = line.strip_prefix("l.") {
+                let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+                msg.push_str(&format!("\n{}", line));
+                return Some((digits.parse().ok(), msg));
+            }
+            if line.starts_with("! ") || line.trim().is_empty() {
+                break;
+            }
+            msg.push_str(&format!("\n{}", line));
         }
+        Some((None, msg))
+    }
+
+    /// Last lines of the output of a program, for error messages
+    fn output_excerpt(output: &[u8]) -> String {
+        const LINES: usize = 10;
+
+        let output = String::from_utf8_lossy(output);
+        let lines: Vec<&str> = output.lines()
+                                     .filter(|l| !l.trim().is_empty())
+                                     .collect();
+        lines[lines.len().saturating_sub(LINES)..].join("\n")
     }
 
     /// Code extraction
-    fn tangle(&self, target: &String, selected: &Option<String>,
-                        out_filename: &Option<String>) -> Result<(), ErrorKind> {
-        let generic_out_name = match out_filename {
+    fn tangle(&self, target: &str, options: &Options) -> Result<(), ErrorKind> {
+        // (<file>, <whether it was or would be changed>)
+        let mut results = Vec::new();
+        for file in self.tangled_files(target, options)? {
+            if file.lines.is_empty() {
+                continue;
+            }
+            let changed = if options.dry_run {
+                Exporter::print_dry_run(&file);
+                file.changed()
+            } else {
+                let written = file.write_content()?;
+                if options.source_map && !file.origins.is_empty() {
+                    file.write_source_map()?;
+                }
+                written
+            };
+            results.push((file, changed));
+        }
+        if let Some(path) = &options.manifest {
+            let manifest = Exporter::manifest(&results);
+            if options.dry_run {
+                print!("{}", manifest);
+            } else {
+                write_file_atomic(path, &manifest)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints which blocks would be written into a file
+    fn print_dry_run(file: &FileContent) {
+        let state = if fs::metadata(&file.name).is_err() {
+            "new"
+        } else if file.changed() {
+            "changed"
+        } else {
+            "unchanged"
+        };
+        println!("{}: {} lines ({})", file.name, file.content().len(), state);
+        for (name, path, line, count) in &file.blocks {
+            println!("    {} ({}:{}): {} lines", name, path, line, count);
+        }
+    }
+
+    /// JSON listing the blocks and line counts of each tangled file
+    fn manifest(files: &[(FileContent, bool)]) -> String {
+        let mut lines = vec!["{".to_string(), " \"files\": [".to_string()];
+        for (i, (file, changed)) in files.iter().enumerate() {
+            lines.push("  {".to_string());
+            lines.push(format!("   \"file\": \"{}\",", json::escape(&file.name)));
+            lines.push(format!("   \"lines\": {},", file.content().len()));
+            lines.push(format!("   \"changed\": {},", changed));
+            lines.push("   \"blocks\": [".to_string());
+            for (j, (name, source, line, count)) in file.blocks.iter().enumerate() {
+                let comma = if j < file.blocks.len()-1 { "," } else { "" };
+                lines.push(format!("    {{\"name\": \"{}\", \"source\": \"{}\", \"line\": {}, \"lines\": {}}}{}",
+                                   json::escape(name),
+                                   json::escape(source),
+                                   line, count, comma));
+            }
+            lines.push("   ]".to_string());
+            let comma = if i < files.len()-1 { "," } else { "" };
+            lines.push(format!("  }}{}", comma));
+        }
+        lines.push(" ]".to_string());
+        lines.push("}".to_string());
+
+        lines.iter().map(|l| format!("{}\n", l)).collect()
+    }
+
+    /// Extracts all blocks in memory and compares the result with the files
+    /// on disk, printing a diff for each file that differs. Returns whether
+    /// all files are up to date.
+    pub fn check(&self, options: &Options) -> Result<bool, ErrorKind> {
+        let mut up_to_date = true;
+        for file in self.tangled_files(".", options)? {
+            if file.lines.is_empty() {
+                continue;
+            }
+            if !file.changed() {
+                continue;
+            }
+            up_to_date = false;
+
+            let (old, old_name) = match fs::metadata(&file.name) {
+                Ok(_)  => (read_file(&file.name)?, file.name.clone()),
+                Err(_) => (Vec::new(), "/dev/null".to_string()),
+            };
+            let diff = unified_diff(&old, &file.content(), &old_name,
+                                    &format!("{} (tangled from {})", file.name,
+                                             self.input_path));
+            if diff.is_empty() {
+                // the lines are equal, so only the bytes ending them differ
+                let mut disk = fs::read(&file.name).unwrap_or_default();
+                let rendered = file.render();
+                if !disk.ends_with(b"\n") {
+                    println!("{}: newline missing at end of file", file.name);
+                    disk.extend(file.line_ending.as_str().as_bytes());
+                }
+                if disk != rendered.as_bytes() {
+                    println!("{}: line endings differ", file.name);
+                }
+            }
+            for line in diff {
+                println!("{}", line);
+            }
+        }
+        Ok(up_to_date)
+    }
+
+    /// Reads compiler or test output from stdin and prints it with locations
+    /// in tangled files replaced by the corresponding org file locations.
+    /// The files are the ones extracting the given format with the same
+    /// options would write.
+    pub fn map_errors(&self, format: &str, options: &Options) -> Result<(), ErrorKind> {
+        let files: Vec<(PathBuf, Vec<Option<Origin>>)> =
+                self.tangled_files(&format.to_lowercase(), options)?
+                    .iter()
+                    .filter(|f| !f.origins.is_empty())
+                    .map(|f| (Exporter::normalize_path(&f.name), f.content_origins()))
+                    .collect();
+
+        let lookup = |path: &str, line: usize, column: Option<usize>| {
+            let normalized = Exporter::normalize_path(path);
+            let origins = &files.iter().find(|f| f.0 == normalized)?.1;
+            let origin = origins.get(line.checked_sub(1)?)?.as_ref()?;
+            let column = column.map(|c| (c as isize + origin.column).max(1) as usize);
+            Some((origin.path.clone(), origin.line, column))
+        };
+
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line.map_err(|_| ErrorKind::FileError {
+                                        msg: "reading from stdin failed".to_string() })?;
+            println!("{}", rewrite_locations(&line, lookup));
+        }
+        Ok(())
+    }
+
+    /// Absolute version of a path if it exists, to compare paths written
+    /// differently
+    fn normalize_path(path: &str) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+    }
+
+    /// Collects the contents of all files produced by code extraction
+    fn tangled_files(&self, target: &str,
+                     options: &Options) -> Result<Vec<FileContent>, ErrorKind> {
+        // with '--output-dir', files are moved from the org file's directory
+        // to the given one
+        let place = |name: &str| match &options.output_dir {
+            Some(root) => reroot_path(root, &parent_dir(&self.input_path), name),
+            None       => name.to_string(),
+        };
+        let generic_out_name = match &options.out_filename {
             Some(s) => s.to_string(),
-            None    => self.output_file_name(target),
+            None    => place(&self.output_file_name(target)),
         };
 
         let mut files = Vec::new();
@@ -344,63 +1078,84 @@ impl Exporter {
             self.src_blocks.clone()
         } else {
             self.src_blocks.iter()
-                .filter(|b| &b.lang == target ||
-                        (&b.lang == "python" &&
-                         target  == "jupyter"))
-                .map(|b| b.clone())
+                .filter(|b| b.lang == target ||
+                        (b.lang == "python" &&
+                         target == "jupyter"))
+                .cloned()
                 .collect()
         };
         
-        match selected {
-            Some(name) => {
-                self.select_blocks(name, &mut target_blocks)?;
+        match &options.block {
+            Some(name) => self.select_blocks(name, &mut target_blocks)?,
+            // without an explicit selection, blocks with ':tangle no' are
+            // left out when extracting to the paths given by ':tangle'
+            None if target == "." => {
+                target_blocks.retain(|b| b.args.get("tangle") != Some("no"));
             },
             None => {},
         }
+        // ':tangle yes' means a file named after the org file
+        for block in target_blocks.iter_mut() {
+            if block.args.get("tangle") == Some("yes") {
+                block.filename = Some(self.output_file_name(&block.lang));
+            }
+            block.filename = block.filename.as_ref().map(|f| place(f));
+        }
+
+        // splice referenced blocks into the ones using noweb syntax
+        let mut contents = Vec::new();
+        for block in &target_blocks {
+            let out_file = match &block.filename {
+                Some(f) if target == "." => f,
+                _                        => &generic_out_name,
+            };
+            let mut lines = self.expand_noweb(block, out_file,
+                                              &mut vec![block.name.clone()])?;
+            if target != "jupyter" {
+                lines = Exporter::add_comments(block, out_file, lines);
+            }
+            contents.push(lines);
+        }
 
         if target == "jupyter" {
-            files.push(FileContent {
-                name:  generic_out_name,
-                lines: Exporter::build_jupyter_notebook(&target_blocks)
-            });
-        } else {
-            if target == "." {
-                Exporter::cp_src_to_files(&mut target_blocks, &mut files);
-            } else { // just export into a single file
-                for block in target_blocks {
-                    if block.lines.len() > 0 {
-                        files[0].lines.append(&mut block.lines.clone());
-                        files[0].lines.push(String::new());
-                    }
+            let cells: Vec<Vec<String>> = contents.into_iter()
+                    .map(|c| c.into_iter().map(|l| l.0).collect())
+                    .collect();
+            let mut notebook = FileContent::new(&generic_out_name);
+            notebook.lines = Exporter::build_jupyter_notebook(&cells);
+            notebook.blocks = target_blocks.iter()
+                    .zip(&cells)
+                    .map(|(b, c)| (b.source_name(), b.path.clone(), b.line, c.len()))
+                    .collect();
+            files.push(notebook);
+        } else if target == "." {
+            Exporter::cp_src_to_files(&target_blocks, contents, &mut files)?;
+        } else { // just export into a single file
+            for (block, lines) in target_blocks.iter().zip(contents) {
+                if !lines.is_empty() {
+                    files[0].append_block(block, lines)?;
                 }
             }
         }
-
-        for file in files {
-            file.write_content()?;
+        // the output directory doesn't necessarily mirror the org file's one
+        if options.output_dir.is_some() {
+            for file in files.iter_mut() {
+                file.mkdirp = true;
+            }
         }
-        Ok(())
+        Ok(files)
     }
 
-    fn cp_src_to_files(target_blocks: &mut Vec<SrcBlock>, files: &mut Vec<FileContent>) {
+    fn cp_src_to_files(target_blocks: &[SrcBlock], contents: Vec<Tangled>,
+                       files: &mut Vec<FileContent>) -> Result<(), ErrorKind> {
         // copy lines of each src block into corresponding FileContent
         // instances, creating them on the go if necessary
-        for block in target_blocks {
-            let mut opt = None;
+        for (block, lines) in target_blocks.iter().zip(contents) {
             // look if there's already a FileContent instance for this path
-            match &block.filename {
-                Some(f) => {
-                    for fi in 0..files.len() {
-                        if &files[fi].name == f {
-                            opt = Some(fi);
-                            break;
-                        }
-                    }
-                },
-                None    => {
-                    opt = Some(0);
-                }
-            }
+            let opt = match &block.filename {
+                Some(f) => files.iter().position(|fi| &fi.name == f),
+                None    => Some(0),
+            };
             // get the index of the FileContent instance, one way or another
             let idx = match opt {
                 None => {
@@ -412,12 +1167,170 @@ impl Exporter {
                 Some(i) => i,
             };
 
-            files[idx].lines.append(&mut block.lines.clone());
-            files[idx].lines.push(String::new());
+            files[idx].append_block(block, lines)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces each '<<name>>' reference in the lines of the given block with
+    /// the (recursively expanded) lines of all blocks of that name. Text in
+    /// front of the reference is repeated on every inserted line, text behind
+    /// it is appended to the last one and may contain further references.
+    /// 'chain' holds the names of the blocks currently being expanded,
+    /// 'out_file' is the file the lines are written to.
+    fn expand_noweb(&self, block: &SrcBlock, out_file: &str,
+                    chain: &mut Vec<String>) -> Result<Tangled, ErrorKind> {
+        let noweb = block.noweb();
+        if noweb == Noweb::No {
+            return Ok(block.tangled_lines());
+        }
+
+        let mut result = Vec::new();
+        for (line, origin) in block.tangled_lines() {
+            // the line being assembled consists of 'head', which is done, and
+            // 'rest', which may contain references
+            let mut head   = String::new();
+            let mut rest   = line;
+            let mut origin = origin;
+            while let Some((before, name, suffix)) = Exporter::parse_noweb_ref(&rest) {
+                let prefix = format!("{}{}", head, before);
+                rest = suffix;
+                if noweb == Noweb::Strip {
+                    head = prefix;
+                    continue;
+                }
+                let mut expanded = self.expand_noweb_ref(block, name, out_file, chain)?;
+
+                let shift = prefix.chars().count() as isize;
+                for (_, o) in expanded.iter_mut() {
+                    if let Some(o) = o.as_mut() {
+                        o.column -= shift;
+                    }
+                }
+                head = match expanded.pop() {
+                    Some((last, o)) => {
+                        for (l, o) in expanded {
+                            result.push((format!("{}{}", prefix, l), o));
+                        }
+                        origin = o;
+                        format!("{}{}", prefix, last)
+                    },
+                    None => prefix,
+                };
+            }
+            result.push((format!("{}{}", head, rest), origin));
+        }
+        Ok(result)
+    }
+
+    /// Lines of all blocks named by a noweb reference in 'block', expanded
+    /// recursively
+    fn expand_noweb_ref(&self, block: &SrcBlock, name: String, out_file: &str,
+                        chain: &mut Vec<String>) -> Result<Tangled, ErrorKind> {
+        if chain.contains(&name) {
+            return Err(ErrorKind::CyclicNowebReference { name });
+        }
+
+        let referenced: Vec<&SrcBlock> = self.src_blocks.iter()
+                                            .filter(|b| b.name == name)
+                                            .collect();
+        if referenced.is_empty() {
+            return Err(ErrorKind::NowebReferenceNotFound { name });
+        }
+
+        chain.push(name);
+        let mut expanded = Vec::new();
+        for r in referenced {
+            let mut lines = self.expand_noweb(r, out_file, chain)?;
+            if block.args.get("comments") == Some("noweb") {
+                lines = Exporter::link_comments(r, &block.lang, out_file, lines);
+            }
+            expanded.append(&mut lines);
         }
+        chain.pop();
+        Ok(expanded)
     }
 
-    fn select_blocks(&self, name: &String,
+    /// Surrounds the lines of a block with comments as set by its
+    /// ':comments' argument: 'link', 'yes' and 'noweb' add links back to the
+    /// org file, 'org' adds the org text in front of the block and 'both'
+    /// adds both.
+    fn add_comments(block: &SrcBlock, out_file: &str, lines: Tangled) -> Tangled {
+        let mode = block.args.get("comments").unwrap_or("no");
+        let mut result = Vec::new();
+
+        if mode == "org" || mode == "both" {
+            let text = &block.anchor.org_text;
+            let first = text.iter().position(|l| !l.trim().is_empty());
+            let last  = text.iter().rposition(|l| !l.trim().is_empty());
+            if let (Some(first), Some(last)) = (first, last) {
+                for line in &text[first..=last] {
+                    match Exporter::comment(&block.lang, line.trim()) {
+                        Some(c) => result.push((c, None)),
+                        None    => break,
+                    }
+                }
+            }
+        }
+
+        match mode {
+            "link" | "yes" | "both" | "noweb" => {
+                result.append(&mut Exporter::link_comments(block, &block.lang,
+                                                           out_file, lines));
+            },
+            _ => result.extend(lines),
+        }
+        result
+    }
+
+    /// Surrounds lines with comments in the given language marking them
+    /// as content of the block and linking back to it
+    fn link_comments(block: &SrcBlock, lang: &str, out_file: &str,
+                     lines: Tangled) -> Tangled {
+        let begin = Exporter::comment(lang, &block.link(out_file));
+        let end   = Exporter::comment(lang, &format!("{} ends here",
+                                                     block.source_name()));
+        match (begin, end) {
+            (Some(b), Some(e)) => {
+                let mut result = vec![(b, None)];
+                result.extend(lines);
+                result.push((e, None));
+                result
+            },
+            _ => lines,
+        }
+    }
+
+    /// Turns text into a comment of the given language, None if the
+    /// language has no known comment syntax
+    fn comment(lang: &str, text: &str) -> Option<String> {
+        let (start, end) = Exporter::comment_syntax(lang)?;
+        Some(if end.is_empty() {
+            format!("{} {}", start, text).trim_end().to_string()
+        } else {
+            format!("{} {} {}", start, text, end)
+        })
+    }
+
+    /// Splits a line at its first '<<name>>' reference into the text before
+    /// the reference, the referenced name and the text after it.
+    /// References to evaluation results like '<<name(x=1)>>' are ignored.
+    fn parse_noweb_ref(line: &str) -> Option<(String, String, String)> {
+        for (start, _) in line.match_indices("<<") {
+            let len  = line[start+2..].find(">>")?;
+            let name = &line[start+2..start+2+len];
+
+            if name.is_empty() || name.trim() != name || name.contains('(') {
+                continue;
+            }
+            return Some((line[..start].to_string(),
+                         name.to_string(),
+                         line[start+4+len..].to_string()));
+        }
+        None
+    }
+
+    fn select_blocks(&self, name: &str,
                     target_blocks: &mut Vec<SrcBlock>) -> Result<(), ErrorKind>{
 
         let mut selected_name = name.to_string();
@@ -431,7 +1344,7 @@ impl Exporter {
         for bi in 0..self.src_blocks.len() {
             if self.src_blocks[bi].name.starts_with(selected_name.as_str()) {
                 prefixes.push(bi);
-                if &self.src_blocks[bi].name == &selected_name {
+                if self.src_blocks[bi].name == selected_name {
                     matches.push(bi);
                 }
             }
@@ -440,10 +1353,10 @@ impl Exporter {
         // block to select
         if matches.len() > 1 {
             return Err(ErrorKind::AmbiguousCodeBlockName);
-        } else if matches.len() == 0 { // no exact match -> autocomplete
+        } else if matches.is_empty() { // no exact match -> autocomplete
             if prefixes.len() > 1 {
                 return Err(ErrorKind::AmbiguousCodeBlockName);
-            } else if prefixes.len() == 0 {
+            } else if prefixes.is_empty() {
                 return Err(ErrorKind::CodeBlockNotFound);
             } else {
                 selected_name = self.src_blocks[prefixes[0]].name.to_owned();
@@ -458,7 +1371,7 @@ impl Exporter {
             for block in &self.src_blocks {
                 if relevant_block_names.contains(&block.name) {
                     for dep in &block.dependencies {
-                        if !relevant_block_names.contains(&dep) {
+                        if !relevant_block_names.contains(dep) {
                             relevant_block_names.push(dep.to_string());
                             added = true;
                         }
@@ -483,7 +1396,7 @@ impl Exporter {
 
                 let mut dependencies_met = true;
                 for dependency in &block.dependencies {
-                    if !inserted_block_names.contains(&dependency) {
+                    if !inserted_block_names.contains(dependency) {
                         dependencies_met = false;
                         break;
                     }
@@ -506,48 +1419,58 @@ impl Exporter {
         Ok(())
     }
 
-    fn mint_tex(&self, lines: &Vec<String>) -> Vec<String> {
-        let mut result = Vec::new();
-        let mut src_idx = 0;
-        let mut src_block = false;
-        let mut pkg = false;
-
-        for i in 0..lines.len() {
-            let line = lines[i].clone();
-            // add import for minted package in header area
-            if !pkg && line.starts_with("\\usepackage") {
-                result.push("\\usepackage{minted}".to_string());
-                pkg = true;
-            }
-            // replace all verbatim src blocks with a specified language
-            if src_idx < self.src_blocks.len() &&
-                line.contains("begin") && line.contains("{verbatim}") &&
-                    self.src_blocks[src_idx].lines.len() > 0 &&
-                    lines[i+1].trim().contains(self.src_blocks[src_idx].lines[0].trim()) &&
-                    self.src_blocks[src_idx].lang != "" {
-
-                        result.push(format!("\\begin{{minted}}{{{}}}",
-                                            self.src_blocks[src_idx].lang));
-                        src_block = true;
-                        src_idx += 1;
-                    } else if line.contains("end") && line.contains("{verbatim}") &&
-                        src_block {
-                            src_block = false;                  
-                            result.push("\\end{minted}".to_string());
-                        } else {    // keep the rest of the code identical
-                            result.push(line);
-                        }
-        }
+    /// Emacs Lisp making Emacs' LaTeX exporter put source blocks into minted
+    /// environments, with the language names of the document's blocks
+    /// translated to the names of Pygments lexers
+    fn minted_setup(&self) -> String {
+        let mut langs: Vec<&str> = self.src_blocks.iter()
+                                                  .map(|b| b.lang.as_str())
+                                                  .filter(|l| !l.is_empty())
+                                                  .collect();
+        langs.sort_unstable();
+        langs.dedup();
+        let lexers: Vec<String> = langs.iter()
+                                       .map(|l| format!("(list (intern {}) {})",
+                                                        elisp_string(l),
+                                                        elisp_string(&Exporter::pygments_lexer(l))))
+                                       .collect();
+        // 'org-latex-listings' was renamed in Org 9.6
+        format!("(require 'ox-latex) \
+                 (setq org-latex-src-block-backend 'minted) \
+                 (setq org-latex-listings 'minted) \
+                 (add-to-list 'org-latex-packages-alist '(\"\" \"minted\")) \
+                 (setq org-latex-minted-langs (list {}))", lexers.join(" "))
+    }
 
-        result
+    /// Name of the Pygments lexer minted uses for a language of org-babel
+    fn pygments_lexer(lang: &str) -> String {
+        let lang = lang.to_lowercase();
+        let lexer = match lang.as_str() {
+            "emacs-lisp" | "elisp"           => "elisp",
+            "lisp"                           => "common-lisp",
+            "sh" | "shell" | "bash" | "zsh"  => "bash",
+            "c++" | "cpp"                    => "cpp",
+            "csharp" | "c#" | "cs"           => "csharp",
+            "js"                             => "javascript",
+            "ts"                             => "typescript",
+            "makefile"                       => "make",
+            "dockerfile"                     => "docker",
+            "conf"                           => "ini",
+            "md"                             => "markdown",
+            "sqlite"                         => "sql",
+            _                                => &lang,
+        };
+        lexer.to_string()
     }
 
-    fn output_file_name(&self, target: &String) -> String {
-        let input_file = self.input_path.split('/').last().unwrap();
-        let prefix     = input_file.split('.').nth(0).unwrap();
+    /// File next to the org file, named after it with the suffix of the
+    /// given language
+    fn output_file_name(&self, target: &str) -> String {
+        let input_file = self.input_path.split('/').next_back().unwrap();
+        let prefix     = input_file.split('.').next().unwrap();
 
-        match target.as_str() {
-            ""                        => format!("{}", prefix),
+        let name = match target {
+            ""                        => prefix.to_string(),
             "awk"                     => format!("{}.awk", prefix),
             "bash" | "sh" | "shell"   => format!("{}.sh", prefix),
             "c"                       => format!("{}.c", prefix),
@@ -579,26 +1502,48 @@ impl Exporter {
             "toml"                    => format!("{}.toml", prefix),
             "yaml"                    => format!("{}.yml", prefix),
             // if unknown, check if the suffix was defined in the input file
-            _ => {
-                for (lang, suffix) in &self.langs {
-                    if lang == target {
-                        return format!("{}.{}", prefix, suffix);
-                    }
-                }
-                prefix.to_string()
+            _ => match self.langs.iter().find(|(lang, _)| lang == target) {
+                Some((_, suffix)) => format!("{}.{}", prefix, suffix),
+                None              => prefix.to_string(),
             },
+        };
+        resolve_path(&self.input_path, &name)
+    }
+
+    /// Comment delimiters (<start>, <end>) of a language, the end being
+    /// empty for line comments
+    fn comment_syntax(lang: &str) -> Option<(&'static str, &'static str)> {
+        match lang.to_lowercase().as_str() {
+            "awk" | "bash" | "sh" | "shell" | "zsh" | "fish" |
+            "conf" | "cmake" | "dockerfile" | "elixir" | "julia" |
+            "makefile" | "nix" | "perl" | "powershell" | "python" |
+            "r" | "ruby" | "tcl" | "toml" | "yaml"          => Some(("#", "")),
+            "c" | "cpp" | "c++" | "csharp" | "c#" | "cs" | "d" |
+            "dart" | "go" | "java" | "js" | "javascript" | "kotlin" |
+            "php" | "rust" | "scala" | "swift" | "typescript" |
+            "ts" | "zig"                                    => Some(("//", "")),
+            "emacs-lisp" | "elisp" | "lisp" | "scheme" |
+            "clojure" | "racket"                            => Some((";;", "")),
+            "haskell" | "lua" | "sql" | "ada"               => Some(("--", "")),
+            "latex" | "tex" | "erlang" | "matlab" |
+            "octave" | "prolog"                             => Some(("%", "")),
+            "ocaml" | "fsharp"                              => Some(("(*", "*)")),
+            "css"                                           => Some(("/*", "*/")),
+            "html" | "xml" | "markdown" | "md"              => Some(("<!--", "-->")),
+            "vim"                                           => Some(("\"", "")),
+            _                                               => None,
         }
     }
 
     /// Generate syntax for a jupyter notebook(aka json) file.
     /// Only exports Python code, no Markdown blocks.
-    fn build_jupyter_notebook(blocks: &Vec<SrcBlock>) -> Vec<String> {
+    fn build_jupyter_notebook(cells: &[Vec<String>]) -> Vec<String> {
         let mut clines = Vec::new();
         clines.push("{".to_string());
         // write cells
         clines.push(" \"cells\": [".to_string());
 
-        for block in blocks {
+        for cell in cells {
             clines.push("  {".to_string());
             clines.push("   \"cell_type\": \"code\",".to_string());
             clines.push("   \"execution_count\": null,".to_string());
@@ -606,22 +1551,18 @@ impl Exporter {
             clines.push("   \"outputs\": [],".to_string());
             clines.push("   \"source\": [".to_string());
 
-            let len = block.lines.len();
-            for k in 0..len {
-                let escaped = block.lines[k].replace("\\", "\\\\")
-                    .replace("\"", "\\\"")
-                    .replace("\t", "    ");
+            // every line but the last one ends with a newline
+            let len = cell.len();
+            for (k, line) in cell.iter().enumerate() {
+                let escaped = json::escape(line);
                 let line = if k < len-1 {
                     format!("    \"{}\\n\",", escaped)
                 } else {
-                    format!("    \"{}\\n\"", escaped)
+                    format!("    \"{}\"", escaped)
                 };
                 clines.push(line);
             }
 
-            let clen = clines.len();
-            clines[clen-1] = clines[clen-1].replace("\\n", "");
-
             clines.push("   ]".to_string());
             clines.push("  },".to_string());
         }
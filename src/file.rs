@@ -1,13 +1,36 @@
 use error::ErrorKind;
 
-use std::fs::{File, OpenOptions};
+use std::env;
+use std::fs::{self, File, OpenOptions};
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
-/// Read file, remove newlines and tabs and return contents or error as Result
-pub fn read_file(path: &String) -> Result<Vec<String>, ErrorKind> {
+/// Line terminator used when writing a file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf   => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Read file, remove line terminators and return contents or error as Result
+pub fn read_file(path: &str) -> Result<Vec<String>, ErrorKind> {
+    Ok(read_lines(path)?.0)
+}
+
+/// Read file, remove line terminators and return contents along with the line
+/// ending used by the first line of the file
+pub fn read_lines(path: &str) -> Result<(Vec<String>, LineEnding), ErrorKind> {
     let file = match OpenOptions::new().read(true).open(path) {
         Err(_) => return Err(ErrorKind::FileError {
                                 msg: format!("{} could not be opened", path) }),
@@ -17,6 +40,7 @@ pub fn read_file(path: &String) -> Result<Vec<String>, ErrorKind> {
     let mut reader = BufReader::new(file);
     let mut lines = Vec::new();
     let mut line = String::new();
+    let mut ending = None;
 
     loop {
         match reader.read_line(&mut line) {
@@ -26,36 +50,236 @@ pub fn read_file(path: &String) -> Result<Vec<String>, ErrorKind> {
                 break;
             },
         }
-        
-        line = line.replace("\n", "");
-        line = line.replace("\t", "    ");
+
+        if line.ends_with('\n') {
+            line.pop();
+            let crlf = line.ends_with('\r');
+            if crlf {
+                line.pop();
+            }
+            if ending.is_none() {
+                ending = Some(if crlf { LineEnding::CrLf } else { LineEnding::Lf });
+            }
+        }
         lines.push(line.clone());
         line.clear();
     }
 
-    Ok(lines)
+    Ok((lines, ending.unwrap_or(LineEnding::Lf)))
 }
 
-pub fn write_file(path: &String, lines: &Vec<String>) -> Result<(), ErrorKind> {
+pub fn write_file(path: &str, lines: &[String],
+                  ending: LineEnding) -> Result<(), ErrorKind> {
     // create/open file
-    let f = match File::create(&Path::new(path)) {
+    let f = match File::create(Path::new(path)) {
         Err(_) => return Err(ErrorKind::FileError {
                             msg: format!("{} could not be created", path) }),
         Ok(f) => f,
     };
     let mut writer = BufWriter::new(&f);
     // write lines
-    for n in 0..lines.len() {
-        match write!(writer, "{}\n", lines[n]) {
-            Err(_)  => return Err(ErrorKind::FileError {
-                            msg: format!("writing to {} failed", path) }),
-            Ok(_)   => {},
+    for line in lines {
+        if write!(writer, "{}{}", line, ending.as_str()).is_err() {
+            return Err(ErrorKind::FileError {
+                            msg: format!("writing to {} failed", path) });
         }
     }
-    match writer.flush() {
-        Err(_)  => return Err(ErrorKind::FileError{
-                            msg: format!("writing to {} failed", path) }),
-        Ok(_)   => {},
+    if writer.flush().is_err() {
+        return Err(ErrorKind::FileError{
+                            msg: format!("writing to {} failed", path) });
     }
     Ok(())
 }
+
+/// Write 'content' into a file unless it already contains exactly that.
+/// The content goes into a temporary file next to the target first, which
+/// is flushed to disk and then replaces it, so the target is never left
+/// half-written, not even by a crash. A symbolic link is written through,
+/// replacing the file it points to. Returns whether the file was written.
+pub fn write_file_atomic(path: &str, content: &str) -> Result<bool, ErrorKind> {
+    let resolved = resolve_symlinks(Path::new(path));
+    let target   = resolved.as_path();
+    if let Ok(existing) = fs::read(target) {
+        if existing == content.as_bytes() {
+            return Ok(false);
+        }
+    }
+
+    let file_name = match target.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None    => return Err(ErrorKind::FileError {
+                            msg: format!("{} is not a file name", path) }),
+    };
+    // the process id keeps several runs writing the same file apart
+    let tmp = target.with_file_name(format!(".{}.{}.exorg-tmp", file_name,
+                                            std::process::id()));
+
+    let result = File::create(&tmp)
+        .and_then(|mut f| f.write_all(content.as_bytes()).and_then(|_| f.sync_all()))
+        .and_then(|_| match fs::metadata(target) {
+            // keep the permissions of the file being replaced
+            Ok(meta) => fs::set_permissions(&tmp, meta.permissions()),
+            Err(_)   => Ok(()),
+        })
+        .and_then(|_| fs::rename(&tmp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return Err(ErrorKind::FileError {
+                            msg: format!("writing to {} failed", path) });
+    }
+    Ok(true)
+}
+
+/// Path a file is actually written to: the target of a symbolic link, even
+/// a dangling one, or else the path itself
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut resolved = path.to_path_buf();
+    // links pointing at each other in a cycle are given up on
+    for _ in 0..40 {
+        match fs::read_link(&resolved) {
+            // relative links are relative to the link's directory
+            Ok(link) => resolved = match resolved.parent() {
+                Some(dir) => dir.join(link),
+                None      => link,
+            },
+            Err(_)   => break,
+        }
+    }
+    resolved
+}
+
+/// Create all missing parent directories of the given path
+pub fn create_parent_dirs(path: &str) -> Result<(), ErrorKind> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            fs::create_dir_all(dir).map_err(|_| ErrorKind::FileError {
+                    msg: format!("directory {} could not be created", dir.display()) })
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Resolves a path given inside the file 'base', e.g. in '#+INCLUDE:' or
+/// ':tangle', the way Emacs does: relative to the directory of 'base', with a
+/// leading '~/' standing for the home directory
+pub fn resolve_path(base: &str, path: &str) -> String {
+    if let (Some(rest), Some(home)) = (path.strip_prefix("~/"), env::var_os("HOME")) {
+        return path_string(&normalize(&Path::new(&home).join(rest)));
+    }
+    let dir = Path::new(base).parent().unwrap_or_else(|| Path::new(""));
+    path_string(&normalize(&dir.join(path)))
+}
+
+/// Directory containing the given file, '' for the working directory
+pub fn parent_dir(path: &str) -> String {
+    Path::new(path).parent()
+                   .map(|d| d.to_string_lossy().to_string())
+                   .unwrap_or_default()
+}
+
+/// Path leading from the directory 'dir' to 'path'
+pub fn relative_path(dir: &str, path: &str) -> String {
+    let dir  = absolute(Path::new(dir));
+    let path = absolute(Path::new(path));
+    let dir_parts:  Vec<Component> = dir.components().collect();
+    let path_parts: Vec<Component> = path.components().collect();
+    let common = dir_parts.iter()
+                          .zip(&path_parts)
+                          .take_while(|(a, b)| a == b)
+                          .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..dir_parts.len() {
+        relative.push("..");
+    }
+    for part in &path_parts[common..] {
+        relative.push(part.as_os_str());
+    }
+    path_string(&relative)
+}
+
+/// Moves 'path' from below the directory 'base' to below 'root'. Paths
+/// outside of 'base' are placed below 'root' with their full absolute path,
+/// like with DESTDIR.
+pub fn reroot_path(root: &str, base: &str, path: &str) -> String {
+    let relative = relative_path(base, path);
+    let inside = Path::new(&relative).components()
+                                     .all(|c| c != Component::ParentDir);
+    let below = if inside {
+        PathBuf::from(relative)
+    } else {
+        absolute(Path::new(path)).components()
+                                 .filter(|c| matches!(c, Component::Normal(_)))
+                                 .collect()
+    };
+    path_string(&normalize(&Path::new(root).join(below)))
+}
+
+/// Removes '.' and resolves '..' components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::CurDir    => {},
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => { result.pop(); },
+                Some(Component::RootDir)   => {},
+                _                          => result.push(".."),
+            },
+            _ => result.push(part.as_os_str()),
+        }
+    }
+    result
+}
+
+/// Absolute and normalized version of a path
+fn absolute(path: &Path) -> PathBuf {
+    match env::current_dir() {
+        Ok(cwd) => normalize(&cwd.join(path)),
+        Err(_)  => normalize(path),
+    }
+}
+
+fn path_string(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
+/// Set the Unix permissions of a file unless it already has them, does
+/// nothing on other platforms
+#[cfg(unix)]
+pub fn set_mode(path: &str, mode: u32) -> Result<(), ErrorKind> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o7777 == mode) {
+        return Ok(());
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|_| ErrorKind::FileError {
+                    msg: format!("permissions of {} could not be set", path) })
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &str, _mode: u32) -> Result<(), ErrorKind> {
+    Ok(())
+}
+
+/// Replace tabs with spaces up to the next multiple of 'width' columns
+pub fn expand_tabs(line: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let n = width - col % width;
+            result.push_str(&" ".repeat(n));
+            col += n;
+        } else {
+            result.push(c);
+            col += 1;
+        }
+    }
+    result
+}
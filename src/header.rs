@@ -0,0 +1,159 @@
+
+/// Header arguments of a source block as in ':tangle foo.py :noweb yes'.
+/// Names are stored without the leading colon.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderArgs {
+    args: Vec<(String, String)>,
+}
+
+/// Defaults org-babel applies to every block
+const DEFAULT_ARGS: [(&str, &str); 7] = [
+    ("session", "none"),
+    ("results", "replace"),
+    ("exports", "code"),
+    ("cache",   "no"),
+    ("noweb",   "no"),
+    ("hlines",  "no"),
+    ("tangle",  "no"),
+];
+
+impl HeaderArgs {
+    pub fn new() -> Self {
+        HeaderArgs { args: Vec::new() }
+    }
+
+    /// Arguments every block starts with before any file or block specific
+    /// ones are applied
+    pub fn defaults() -> Self {
+        let mut args = HeaderArgs::new();
+        for (name, value) in DEFAULT_ARGS.iter() {
+            args.set(name, value);
+        }
+        args
+    }
+
+    /// Parses a string of header arguments. Anything in front of the first
+    /// argument name is ignored, values may consist of multiple words.
+    pub fn parse(s: &str) -> Self {
+        let mut args  = HeaderArgs::new();
+        let mut name: Option<String> = None;
+        let mut value = Vec::new();
+
+        for token in tokenize(s) {
+            if token.starts_with(':') && token.len() > 1 {
+                if let Some(n) = name.take() {
+                    args.set(&n, &join_value(&value));
+                }
+                name = Some(token[1..].to_lowercase());
+                value.clear();
+            } else if name.is_some() {
+                value.push(token);
+            }
+        }
+        if let Some(n) = name {
+            args.set(&n, &join_value(&value));
+        }
+        args
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.args.iter()
+                 .find(|(n, _)| n == name)
+                 .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.args.iter_mut().find(|(n, _)| n == name) {
+            Some(arg) => arg.1 = value.to_string(),
+            None      => self.args.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// All arguments in the order they were set
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String)> {
+        self.args.iter()
+    }
+
+    /// Applies all arguments of 'other', overriding those with the same name
+    pub fn merge(&mut self, other: &HeaderArgs) {
+        for (name, value) in &other.args {
+            self.set(name, value);
+        }
+    }
+}
+
+/// Splits at whitespace, keeping quoted strings and parenthesized
+/// expressions like '(identity #o755)' together
+pub fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens  = Vec::new();
+    let mut current = String::new();
+    let mut depth   = 0;
+    let mut quoted  = false;
+    let mut escaped = false;
+
+    for c in s.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if quoted             => { current.push(c); escaped = true; },
+            '"'                        => { current.push(c); quoted = !quoted; },
+            '(' if !quoted             => { current.push(c); depth += 1; },
+            ')' if !quoted && depth > 0 => { current.push(c); depth -= 1; },
+            c if c.is_whitespace() && !quoted && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Joins the words of a value, removing the quotes of a single string
+fn join_value(words: &[String]) -> String {
+    if words.len() == 1 {
+        return unquote(&words[0]);
+    }
+    words.join(" ")
+}
+
+/// Removes the quotes and escapes of a quoted string, other strings are
+/// returned as they are
+pub fn unquote(word: &str) -> String {
+    if word.len() >= 2 && word.starts_with('"') && word.ends_with('"') {
+        return word[1..word.len()-1].replace("\\\"", "\"")
+                                    .replace("\\\\", "\\");
+    }
+    word.to_string()
+}
+
+/// Interprets a ':tangle-mode' value like '(identity #o755)', 'o755' or
+/// 'rwxr-xr-x' as Unix file permissions
+pub fn parse_file_mode(value: &str) -> Option<u32> {
+    let mut mode = value.trim();
+    if mode.starts_with("(identity ") && mode.ends_with(')') {
+        mode = mode[10..mode.len()-1].trim();
+    }
+
+    if mode.len() == 9 && mode.chars().all(|c| "rwx-".contains(c)) {
+        let mut bits = 0;
+        for (i, c) in mode.chars().enumerate() {
+            if c != '-' && "rwx".chars().nth(i % 3) != Some(c) {
+                return None;
+            }
+            bits = bits << 1 | (c != '-') as u32;
+        }
+        return Some(bits);
+    }
+
+    let octal = mode.trim_start_matches('#').strip_prefix('o')?;
+    u32::from_str_radix(octal, 8).ok().filter(|m| *m <= 0o7777)
+}
@@ -0,0 +1,182 @@
+/// Kinds of tokens told apart when highlighting source code
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+/// What's needed to highlight a language
+struct Syntax {
+    keywords:      &'static [&'static str],
+    line_comment:  &'static [&'static str],
+    // (<start>, <end>)
+    block_comment: Option<(&'static str, &'static str)>,
+    // characters enclosing strings
+    quotes:        &'static str,
+    // whether keywords are recognized in any case, as in SQL
+    ignore_case:   bool,
+}
+
+const PYTHON: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break",
+    "class", "continue", "def", "del", "elif", "else", "except", "finally",
+    "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+    "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+const RUST: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn",
+    "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let",
+    "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+    "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while",
+];
+const C: &[&str] = &[
+    "auto", "bool", "break", "case", "char", "class", "const", "continue",
+    "default", "delete", "do", "double", "else", "enum", "extern", "false",
+    "float", "for", "goto", "if", "inline", "int", "long", "namespace", "new",
+    "nullptr", "private", "protected", "public", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "template", "this", "true",
+    "typedef", "typename", "union", "unsigned", "using", "virtual", "void",
+    "volatile", "while",
+];
+const JAVA: &[&str] = &[
+    "abstract", "boolean", "break", "byte", "case", "catch", "char", "class",
+    "continue", "default", "do", "double", "else", "enum", "extends", "false",
+    "final", "finally", "float", "for", "if", "implements", "import",
+    "instanceof", "int", "interface", "long", "new", "null", "package",
+    "private", "protected", "public", "return", "short", "static", "super",
+    "switch", "synchronized", "this", "throw", "throws", "true", "try", "void",
+    "while",
+];
+const JS: &[&str] = &[
+    "async", "await", "break", "case", "catch", "class", "const", "continue",
+    "default", "delete", "do", "else", "export", "extends", "false", "finally",
+    "for", "function", "if", "import", "in", "instanceof", "let", "new",
+    "null", "return", "super", "switch", "this", "throw", "true", "try",
+    "typeof", "undefined", "var", "void", "while", "yield",
+];
+const GO: &[&str] = &[
+    "break", "case", "chan", "const", "continue", "default", "defer", "else",
+    "false", "for", "func", "go", "goto", "if", "import", "interface", "map",
+    "nil", "package", "range", "return", "select", "struct", "switch", "true",
+    "type", "var",
+];
+const SHELL: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for",
+    "function", "if", "in", "local", "return", "then", "until", "while",
+];
+const LISP: &[&str] = &[
+    "cond", "defconst", "defcustom", "defmacro", "defun", "defvar", "if",
+    "lambda", "let", "let*", "progn", "provide", "require", "setq", "unless",
+    "when",
+];
+const RUBY: &[&str] = &[
+    "begin", "class", "def", "do", "else", "elsif", "end", "false", "if",
+    "module", "nil", "require", "rescue", "return", "self", "true", "unless",
+    "until", "while", "yield",
+];
+const SQL: &[&str] = &[
+    "and", "as", "by", "create", "delete", "drop", "from", "group", "insert",
+    "into", "join", "not", "null", "on", "or", "order", "select", "set",
+    "table", "update", "values", "where",
+];
+
+fn syntax(lang: &str) -> Option<Syntax> {
+    let ignore_case = lang.eq_ignore_ascii_case("sql");
+    let syntax = |keywords, line_comment, block_comment, quotes| Some(Syntax {
+        keywords, line_comment, block_comment, quotes, ignore_case,
+    });
+    match lang.to_lowercase().as_str() {
+        "python"                              => syntax(PYTHON, &["#"], None, "\"'"),
+        "rust"                                => syntax(RUST, &["//"], Some(("/*", "*/")), "\""),
+        "c" | "cpp" | "c++" | "d"             => syntax(C, &["//"], Some(("/*", "*/")), "\"'"),
+        "java" | "kotlin" | "scala" |
+        "csharp" | "c#" | "cs"                => syntax(JAVA, &["//"], Some(("/*", "*/")), "\"'"),
+        "js" | "javascript" | "ts" |
+        "typescript"                          => syntax(JS, &["//"], Some(("/*", "*/")), "\"'`"),
+        "go"                                  => syntax(GO, &["//"], Some(("/*", "*/")), "\"'`"),
+        "sh" | "bash" | "shell" | "zsh"       => syntax(SHELL, &["#"], None, "\"'"),
+        "emacs-lisp" | "elisp" | "lisp" |
+        "scheme" | "clojure"                  => syntax(LISP, &[";"], None, "\""),
+        "ruby"                                => syntax(RUBY, &["#"], None, "\"'"),
+        "sql"                                 => syntax(SQL, &["--"], Some(("/*", "*/")), "'\""),
+        "css"                                 => syntax(&[], &[], Some(("/*", "*/")), "\"'"),
+        "yaml" | "toml" | "conf" | "makefile" |
+        "perl" | "r" | "julia"                => syntax(&[], &["#"], None, "\"'"),
+        "lua" | "haskell"                     => syntax(&[], &["--"], None, "\"'"),
+        "latex" | "tex" | "matlab" | "octave" => syntax(&[], &["%"], None, ""),
+        _                                     => None,
+    }
+}
+
+/// Splits source code into tokens, all of it being plain for unknown
+/// languages
+pub fn tokenize(lang: &str, code: &str) -> Vec<(Token, String)> {
+    let syntax = match syntax(lang) {
+        Some(s) => s,
+        None    => return vec![(Token::Plain, code.to_string())],
+    };
+    let chars: Vec<char> = code.chars().collect();
+    let starts_with = |i: usize, s: &str| s.chars().enumerate()
+                                           .all(|(n, c)| chars.get(i + n) == Some(&c));
+    let find = |i: usize, s: &str| (i..chars.len()).find(|&j| starts_with(j, s));
+
+    let mut tokens: Vec<(Token, String)> = Vec::new();
+    let mut push = |token: Token, text: &[char]| {
+        match tokens.last_mut() {
+            Some((last, s)) if *last == token => s.extend(text),
+            _ => tokens.push((token, text.iter().collect())),
+        }
+    };
+    // lisp symbols like 'let*' or 'org-mode' include more characters
+    let lisp = syntax.line_comment == [";"];
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' ||
+                            (lisp && (c == '-' || c == '*'));
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let end = if let Some(&prefix) = syntax.line_comment.iter()
+                                                             .find(|p| starts_with(i, p)) {
+            let end = find(i + prefix.len(), "\n").unwrap_or(chars.len());
+            push(Token::Comment, &chars[i..end]);
+            end
+        } else if let Some((start, stop)) = syntax.block_comment.filter(|(s, _)| starts_with(i, s)) {
+            let end = find(i + start.len(), stop).map_or(chars.len(), |e| e + stop.len());
+            push(Token::Comment, &chars[i..end]);
+            end
+        } else if syntax.quotes.contains(c) {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(chars.len());
+            push(Token::String, &chars[i..end]);
+            end
+        } else if c.is_ascii_digit() && (i == 0 || !is_word(chars[i-1])) {
+            let end = (i..chars.len()).find(|&j| !chars[j].is_ascii_alphanumeric() &&
+                                                 chars[j] != '.' && chars[j] != '_')
+                                      .unwrap_or(chars.len());
+            push(Token::Number, &chars[i..end]);
+            end
+        } else if is_word(c) {
+            let end = (i..chars.len()).find(|&j| !is_word(chars[j]))
+                                      .unwrap_or(chars.len());
+            let word: String = chars[i..end].iter().collect();
+            let keyword = syntax.keywords.iter().any(|k| if syntax.ignore_case {
+                k.eq_ignore_ascii_case(&word)
+            } else {
+                *k == word
+            });
+            push(if keyword { Token::Keyword } else { Token::Plain }, &chars[i..end]);
+            end
+        } else {
+            push(Token::Plain, &chars[i..i+1]);
+            i + 1
+        };
+        i = end;
+    }
+    tokens
+}
@@ -0,0 +1,456 @@
+use std::path::Path;
+
+use document::{Document, Element, Headline, ListItem, Scopes, Table, TableRow};
+use highlight::{tokenize, Token};
+use markup::{self, Inline, Link, Section};
+
+/// Extensions of the images shown by an img element
+const IMAGES: [&str; 6] = ["png", "jpg", "jpeg", "gif", "svg", "webp"];
+
+/// Style sheet embedded into every page
+const STYLE: &str = "
+body { margin: 0; font-family: sans-serif; line-height: 1.5; color: #222; }
+#content { max-width: 50em; margin: 0 auto; padding: 1em 2em; }
+.title { text-align: center; margin-bottom: 0; }
+.subtitle { text-align: center; color: #666; margin-top: .3em; }
+#table-of-contents ul { list-style: none; padding-left: 1.2em; }
+h2, h3, h4, h5, h6 { margin-top: 1.6em; }
+.anchor { margin-left: .4em; color: #bbb; text-decoration: none; visibility: hidden; }
+h2:hover .anchor, h3:hover .anchor, h4:hover .anchor,
+h5:hover .anchor, h6:hover .anchor { visibility: visible; }
+.section-number { margin-right: .5em; }
+.todo { color: #c00; font-size: .8em; }
+.done { color: #080; font-size: .8em; }
+.tag { float: right; font-size: .7em; font-weight: normal; background: #eee;
+       border-radius: 3px; padding: 0 .4em; margin-left: .3em; }
+code { background: #f4f4f4; border-radius: 3px; padding: 0 .2em; }
+.src-block { position: relative; margin: 1em 0; }
+.src-lang { position: absolute; top: 0; right: 0; font-size: .75em; color: #666;
+            background: #e8e8e8; border-radius: 0 4px 0 4px; padding: 0 .5em; }
+pre { background: #f8f8f8; border: 1px solid #ddd; border-radius: 4px;
+      padding: .8em; overflow-x: auto; line-height: 1.3; }
+pre code { background: none; padding: 0; }
+.k { color: #a626a4; font-weight: bold; }
+.s { color: #50a14f; }
+.n { color: #986801; }
+.c { color: #a0a1a7; font-style: italic; }
+table { border-collapse: collapse; margin: 1em auto; }
+th, td { border: 1px solid #ccc; padding: .2em .6em; }
+th { background: #f0f0f0; }
+td.num { text-align: right; }
+blockquote { border-left: 4px solid #ddd; margin-left: 0; padding-left: 1em; color: #555; }
+.org-center { text-align: center; }
+.underline { text-decoration: underline; }
+img { max-width: 100%; }
+";
+
+/// Converts an org document into a standalone HTML page with a table of
+/// contents
+pub fn to_html(document: &Document) -> Vec<String> {
+    let sections = markup::sections(document, &mut |h, number| match h.custom_id() {
+        Some(id) => id.to_string(),
+        None     => format!("sec-{}", number.replace('.', "-")),
+    });
+    let mut writer = Writer {
+        lines:    Vec::new(),
+        sections,
+        section:  0,
+        numbered: true,
+    };
+
+    let mut toc = true;
+    for option in document.keyword("OPTIONS").unwrap_or("").split_whitespace() {
+        match option {
+            "toc:nil" => toc = false,
+            "num:nil" => writer.numbered = false,
+            _         => {},
+        }
+    }
+    let stem   = Path::new(&document.path).file_stem()
+                                          .map(|s| s.to_string_lossy().to_string())
+                                          .unwrap_or_default();
+    let title  = markup::parse(document.keyword("TITLE").unwrap_or(&stem));
+    let author = markup::parse(document.keyword("AUTHOR").unwrap_or(""));
+    let date   = markup::parse(document.keyword("DATE").unwrap_or(""));
+
+    writer.lines.push("<!DOCTYPE html>".to_string());
+    writer.lines.push("<html lang=\"en\">".to_string());
+    writer.lines.push("<head>".to_string());
+    writer.lines.push("<meta charset=\"utf-8\">".to_string());
+    writer.lines.push("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">".to_string());
+    writer.lines.push(format!("<title>{}</title>", escape(&markup::plain_text(&title))));
+    if !author.is_empty() {
+        writer.lines.push(format!("<meta name=\"author\" content=\"{}\">",
+                                  escape(&markup::plain_text(&author))));
+    }
+    writer.lines.push("<style>".to_string());
+    writer.lines.extend(STYLE.trim().lines().map(|l| l.to_string()));
+    writer.lines.push("</style>".to_string());
+    for element in &document.elements {
+        if let Element::Keyword(k) = element {
+            if k.key == "HTML_HEAD" {
+                writer.lines.push(k.value.clone());
+            }
+        }
+    }
+    writer.lines.push("</head>".to_string());
+    writer.lines.push("<body>".to_string());
+    writer.lines.push("<div id=\"content\">".to_string());
+    writer.lines.push(format!("<h1 class=\"title\">{}</h1>", writer.inline(&title)));
+    let byline: Vec<String> = [&author, &date].iter()
+                                              .filter(|i| !i.is_empty())
+                                              .map(|i| writer.inline(i))
+                                              .collect();
+    if !byline.is_empty() {
+        writer.lines.push(format!("<p class=\"subtitle\">{}</p>", byline.join(" &mdash; ")));
+    }
+    if toc && !writer.sections.is_empty() {
+        writer.table_of_contents();
+    }
+
+    writer.elements(document, &Scopes::default());
+    writer.lines.push("</div>".to_string());
+    writer.lines.push("</body>".to_string());
+    writer.lines.push("</html>".to_string());
+    writer.lines
+}
+
+struct Writer {
+    lines:    Vec<String>,
+    // each exported headline, in order
+    sections: Vec<Section>,
+    // index of the next headline in 'sections'
+    section:  usize,
+    numbered: bool,
+}
+
+impl Writer {
+    /// Nested lists of links to all headlines
+    fn table_of_contents(&mut self) {
+        let mut entries = Vec::new();
+        // levels of the open lists
+        let mut open: Vec<usize> = Vec::new();
+        for section in &self.sections {
+            while open.last().is_some_and(|level| *level > section.level) {
+                entries.push("</li></ul>".to_string());
+                open.pop();
+            }
+            if open.last().is_none_or(|level| *level < section.level) {
+                entries.push("<ul>".to_string());
+                open.push(section.level);
+            } else {
+                entries.push("</li>".to_string());
+            }
+            let number = if self.numbered {
+                format!("<span class=\"section-number\">{}</span>", section.number)
+            } else {
+                String::new()
+            };
+            let title = markup::plain_text(&markup::parse(&section.title));
+            entries.push(format!("<li><a href=\"#{}\">{}{}</a>", escape(&section.anchor),
+                                 number, escape(&title)));
+        }
+        while open.pop().is_some() {
+            entries.push("</li></ul>".to_string());
+        }
+
+        self.lines.push("<nav id=\"table-of-contents\">".to_string());
+        self.lines.push("<h2>Table of Contents</h2>".to_string());
+        self.lines.append(&mut entries);
+        self.lines.push("</nav>".to_string());
+    }
+
+    /// Writes the exported elements of a document, whose headlines are
+    /// nested in the 'outer' ones
+    fn elements(&mut self, document: &Document, outer: &Scopes) {
+        let mut scopes = Scopes::enter_document(outer, document);
+        for element in document.exported() {
+            match element {
+                Element::Headline(h)  => {
+                    scopes.enter_headline(h);
+                    self.headline(h);
+                },
+                Element::Paragraph(p) => {
+                    let text = self.text(&p.lines);
+                    self.lines.push(text);
+                },
+                Element::List(l)  => {
+                    let mut lines = self.list(&l.items);
+                    self.lines.append(&mut lines);
+                },
+                Element::Table(t) => self.table(t),
+                Element::Src(b) => if b.exported(&scopes) {
+                    self.src_block(&b.lang, &b.lines);
+                },
+                Element::Example(b) => {
+                    self.lines.push(format!("<pre class=\"example\">{}</pre>",
+                                            escape(&b.lines.join("\n"))));
+                },
+                Element::Block(b) => {
+                    let backend = b.params.split_whitespace().next().unwrap_or("");
+                    match b.kind.as_str() {
+                        "export" if backend.eq_ignore_ascii_case("html") => {
+                            self.lines.extend(b.lines.iter().cloned());
+                        },
+                        "export" | "comment" => {},
+                        "quote" => {
+                            let text = self.text(&b.lines);
+                            self.lines.push(format!("<blockquote>\n{}\n</blockquote>", text));
+                        },
+                        "verse" => {
+                            let lines: Vec<String> = b.lines.iter()
+                                .map(|l| self.inline(&markup::parse(l.trim())))
+                                .collect();
+                            self.lines.push(format!("<p class=\"verse\">\n{}\n</p>",
+                                                    lines.join("<br>\n")));
+                        },
+                        // 'center' and special blocks like '#+BEGIN_note'
+                        // become a div of that class
+                        kind => {
+                            let text = self.text(&b.lines);
+                            self.lines.push(format!("<div class=\"org-{}\">\n{}\n</div>",
+                                                    escape(kind), text));
+                        },
+                    }
+                },
+                Element::Keyword(k) if k.key == "HTML" => {
+                    self.lines.push(k.value.clone());
+                },
+                Element::Include(i) => if let Some(content) = &i.content {
+                    self.elements(content, &scopes);
+                },
+                Element::Keyword(_) => {},
+            }
+        }
+    }
+
+    fn headline(&mut self, h: &Headline) {
+        let section = &self.sections[self.section];
+        self.section += 1;
+
+        let tag = format!("h{}", (h.level + 1).min(6));
+        let mut html = format!("<{} id=\"{}\">", tag, escape(&section.anchor));
+        if self.numbered {
+            html.push_str(&format!("<span class=\"section-number\">{}</span>", section.number));
+        }
+        if let Some(todo) = &h.todo {
+            html.push_str(&format!("<span class=\"{}\">{}</span> ",
+                                   todo.to_lowercase(), escape(todo)));
+        }
+        html.push_str(&self.inline(&markup::parse(&h.title)));
+        for tag in &h.tags {
+            html.push_str(&format!("<span class=\"tag\">{}</span>", escape(tag)));
+        }
+        html.push_str(&format!("<a class=\"anchor\" href=\"#{}\">&para;</a></{}>",
+                               escape(&section.anchor), tag));
+        self.lines.push(html);
+    }
+
+    /// Nested ul, ol and dl elements of the items of a list. Items are on
+    /// the level of the first one unless they are indented further.
+    fn list(&self, items: &[ListItem]) -> Vec<String> {
+        let kind = |item: &ListItem| if item.tag.is_some() {
+            "dl"
+        } else if item.ordered() {
+            "ol"
+        } else {
+            "ul"
+        };
+        let level = items[0].indent;
+        let mut lines = Vec::new();
+        let mut open: Option<&str> = None;
+        let mut i = 0;
+        while i < items.len() {
+            let item = &items[i];
+            let children = items[i+1..].iter()
+                                       .take_while(|c| c.indent > item.indent.max(level))
+                                       .count();
+            if let Some(o) = open.filter(|o| *o != kind(item)) {
+                lines.push(format!("</{}>", o));
+                open = None;
+            }
+            if open.is_none() {
+                lines.push(format!("<{}>", kind(item)));
+                open = Some(kind(item));
+            }
+
+            // items of a single paragraph need none around it
+            let mut content = if item.lines.iter().any(|l| l.is_empty()) {
+                self.text(&item.lines)
+            } else {
+                let text: Vec<&str> = item.lines.iter().map(|l| l.trim()).collect();
+                self.inline(&markup::parse(&text.join("\n")))
+            };
+            if children > 0 {
+                content.push('\n');
+                content.push_str(&self.list(&items[i+1..i+1+children]).join("\n"));
+            }
+            match (&item.tag, item.checkbox) {
+                (Some(tag), _) => {
+                    lines.push(format!("<dt>{}</dt>", self.inline(&markup::parse(tag))));
+                    lines.push(format!("<dd>{}</dd>", content));
+                },
+                (None, Some(state)) => {
+                    let (class, mark) = match state {
+                        ' ' => ("off",   "&#xa0;"),
+                        '-' => ("trans", "-"),
+                        _   => ("on",    "X"),
+                    };
+                    lines.push(format!("<li class=\"{}\"><code>[{}]</code> {}</li>",
+                                       class, mark, content));
+                },
+                (None, None) => lines.push(format!("<li>{}</li>", content)),
+            }
+            i += 1 + children;
+        }
+        if let Some(o) = open {
+            lines.push(format!("</{}>", o));
+        }
+        lines
+    }
+
+    /// Table whose rows in front of the first rule form the header, with
+    /// right-aligned columns of mostly numbers as in org
+    fn table(&mut self, table: &Table) {
+        // a header needs rows in front of the first rule and some after it
+        let first_rule = table.rows.iter().position(|r| *r == TableRow::Rule);
+        let header_rows = match first_rule {
+            Some(r) if r > 0 && table.rows[r..].iter()
+                                               .any(|r| matches!(r, TableRow::Cells(_))) => r,
+            _ => 0,
+        };
+        let columns = table.columns();
+        let numeric = table.numeric_columns(0);
+
+        self.lines.push("<table>".to_string());
+        for (i, row) in table.rows.iter().enumerate() {
+            let cells = match row {
+                TableRow::Cells(cells) => cells,
+                TableRow::Rule         => continue,
+            };
+            let header = i < header_rows;
+            let cells: Vec<String> = (0..columns).map(|c| {
+                let content = cells.get(c).map_or(String::new(),
+                                                  |s| self.inline(&markup::parse(s.trim())));
+                if header {
+                    format!("<th>{}</th>", content)
+                } else if numeric[c] {
+                    format!("<td class=\"num\">{}</td>", content)
+                } else {
+                    format!("<td>{}</td>", content)
+                }
+            }).collect();
+            self.lines.push(format!("<tr>{}</tr>", cells.concat()));
+        }
+        self.lines.push("</table>".to_string());
+    }
+
+    /// Highlighted source code labeled with its language
+    fn src_block(&mut self, lang: &str, lines: &[String]) {
+        let mut code = String::new();
+        for (token, text) in tokenize(lang, &lines.join("\n")) {
+            let class = match token {
+                Token::Plain   => { code.push_str(&escape(&text)); continue; },
+                Token::Keyword => "k",
+                Token::String  => "s",
+                Token::Number  => "n",
+                Token::Comment => "c",
+            };
+            // spans end at line ends, to keep lines intact
+            let spans: Vec<String> = text.split('\n')
+                                         .map(|t| if t.is_empty() {
+                                             String::new()
+                                         } else {
+                                             format!("<span class=\"{}\">{}</span>", class, escape(t))
+                                         })
+                                         .collect();
+            code.push_str(&spans.join("\n"));
+        }
+
+        self.lines.push("<div class=\"src-block\">".to_string());
+        if !lang.is_empty() {
+            self.lines.push(format!("<div class=\"src-lang\">{}</div>", escape(lang)));
+        }
+        self.lines.push(format!("<pre class=\"src src-{}\"><code>{}</code></pre>",
+                                escape(lang), code));
+        self.lines.push("</div>".to_string());
+    }
+
+    /// Paragraphs of text
+    fn text(&self, lines: &[String]) -> String {
+        markup::paragraphs(lines).iter()
+                                 .map(|p| format!("<p>{}</p>", self.inline(&markup::parse(p))))
+                                 .collect::<Vec<String>>()
+                                 .join("\n")
+    }
+
+    fn inline(&self, inlines: &[Inline]) -> String {
+        let mut html = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(s)      => html.push_str(&escape(s)),
+                Inline::Bold(c)      => html.push_str(&format!("<b>{}</b>", self.inline(c))),
+                Inline::Italic(c)    => html.push_str(&format!("<i>{}</i>", self.inline(c))),
+                Inline::Underline(c) => html.push_str(&format!("<span class=\"underline\">{}</span>",
+                                                               self.inline(c))),
+                Inline::Strike(c)    => html.push_str(&format!("<del>{}</del>", self.inline(c))),
+                Inline::Verbatim(s) |
+                Inline::Code(s)      => html.push_str(&format!("<code>{}</code>", escape(s))),
+                Inline::Latex(s) if s == "\\\\" => html.push_str("<br>"),
+                Inline::Latex(s)     => html.push_str(&escape(s)),
+                Inline::Link { target, description } => {
+                    html.push_str(&self.link(target, description));
+                },
+            }
+        }
+        html
+    }
+
+    fn link(&self, target: &str, description: &[Inline]) -> String {
+        let text = if description.is_empty() {
+            escape(target)
+        } else {
+            self.inline(description)
+        };
+        match markup::resolve_link(target, description, &self.sections, &IMAGES) {
+            Link::Section(section) => {
+                let text = if description.is_empty() {
+                    escape(&markup::plain_text(&markup::parse(&section.title)))
+                } else {
+                    text
+                };
+                format!("<a href=\"#{}\">{}</a>", escape(&section.anchor), text)
+            },
+            Link::Image(path) => format!("<img src=\"{}\" alt=\"{}\">", escape(path), escape(path)),
+            // remote images are shown as well
+            Link::Url(url) if description.is_empty() && markup::is_image(url, &IMAGES) => {
+                format!("<img src=\"{}\" alt=\"{}\">", escape(url), escape(url))
+            },
+            Link::Url(url)   => format!("<a href=\"{}\">{}</a>", escape(url), text),
+            Link::File(path) => {
+                // links to org files lead to their exported pages
+                let href = match path.strip_suffix(".org") {
+                    Some(stem) if !path.contains("://") => format!("{}.html", stem),
+                    _                                   => path.to_string(),
+                };
+                format!("<a href=\"{}\">{}</a>", escape(&href), text)
+            },
+            Link::Other => text,
+        }
+    }
+}
+
+/// Escapes characters with a special meaning in HTML
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c   => escaped.push(c),
+        }
+    }
+    escaped
+}
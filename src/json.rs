@@ -0,0 +1,45 @@
+
+/// Escape a string for use inside a JSON string literal
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"'  => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quoted JSON string
+pub fn string(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Quoted JSON string or null
+pub fn optional(s: Option<&str>) -> String {
+    s.map_or("null".to_string(), string)
+}
+
+/// JSON array of already encoded values
+pub fn array<I: IntoIterator<Item = String>>(values: I) -> String {
+    format!("[{}]", values.into_iter().collect::<Vec<String>>().join(","))
+}
+
+/// JSON object of already encoded values
+pub fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields.iter()
+                                    .map(|(name, value)| format!("{}:{}", string(name), value))
+                                    .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Name/value pairs as array of two-element arrays, keeping their order
+pub fn pairs<'a, I: IntoIterator<Item = &'a (String, String)>>(pairs: I) -> String {
+    array(pairs.into_iter().map(|(n, v)| array(vec![string(n), string(v)])))
+}
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use document::{Document, Element, Headline, List, Scopes, Table, TableRow};
+use file::expand_tabs;
+use markup::{self, Inline, Link, Section};
+
+/// Sectioning commands of the headline levels, deeper ones using the last
+const SECTIONS: [&str; 5] = ["section", "subsection", "subsubsection",
+                             "paragraph", "subparagraph"];
+
+/// Extensions of the images '\includegraphics' can show
+const IMAGES: [&str; 6] = ["png", "jpg", "jpeg", "gif", "pdf", "eps"];
+
+/// Packages loaded by every document, the same ones Emacs uses by default
+const PACKAGES: [&str; 11] = [
+    "\\usepackage[utf8]{inputenc}",
+    "\\usepackage[T1]{fontenc}",
+    "\\usepackage{graphicx}",
+    "\\usepackage{longtable}",
+    "\\usepackage{wrapfig}",
+    "\\usepackage{rotating}",
+    "\\usepackage[normalem]{ulem}",
+    "\\usepackage{amsmath}",
+    "\\usepackage{amssymb}",
+    "\\usepackage{capt-of}",
+    "\\usepackage{hyperref}",
+];
+
+/// Converts an org document into LaTeX, laid out like the output of Emacs'
+/// LaTeX exporter
+pub fn to_latex(document: &Document) -> Vec<String> {
+    // labels are the custom ids or the numbers of the headlines in order
+    let mut count = 0;
+    let sections = markup::sections(document, &mut |h, _| {
+        count += 1;
+        match h.custom_id() {
+            Some(id) => format!("sec:{}", id),
+            None     => format!("sec:{}", count),
+        }
+    });
+    let mut writer = Writer {
+        lines:    Vec::new(),
+        sections,
+        section:  0,
+        numbered: true,
+    };
+
+    let mut toc = true;
+    for option in document.keyword("OPTIONS").unwrap_or("").split_whitespace() {
+        match option {
+            "toc:nil" => toc = false,
+            "num:nil" => writer.numbered = false,
+            _         => {},
+        }
+    }
+    let stem  = Path::new(&document.path).file_stem()
+                                         .map(|s| s.to_string_lossy().to_string())
+                                         .unwrap_or_default();
+    let title  = markup::parse(document.keyword("TITLE").unwrap_or(&stem));
+    let author = markup::parse(document.keyword("AUTHOR").unwrap_or(""));
+
+    writer.lines.push(format!("\\documentclass[{}]{{{}}}",
+                              document.keyword("LATEX_CLASS_OPTIONS")
+                                      .map_or("11pt", |o| o.trim_matches(['[', ']'])),
+                              document.keyword("LATEX_CLASS").unwrap_or("article")));
+    writer.lines.extend(PACKAGES.iter().map(|p| p.to_string()));
+    for element in &document.elements {
+        if let Element::Keyword(k) = element {
+            if k.key == "LATEX_HEADER" {
+                writer.lines.push(k.value.clone());
+            }
+        }
+    }
+    writer.lines.push(format!("\\author{{{}}}", writer.inline(&author)));
+    writer.lines.push(match document.keyword("DATE") {
+        Some(date) => format!("\\date{{{}}}", writer.inline(&markup::parse(date))),
+        None       => "\\date{\\today}".to_string(),
+    });
+    writer.lines.push(format!("\\title{{{}}}", writer.inline(&title)));
+    writer.lines.push("\\hypersetup{".to_string());
+    writer.lines.push(format!(" pdfauthor={{{}}},", escape(&markup::plain_text(&author))));
+    writer.lines.push(format!(" pdftitle={{{}}},", escape(&markup::plain_text(&title))));
+    writer.lines.push(" pdflang={English}}".to_string());
+    writer.lines.push("\\begin{document}".to_string());
+    writer.lines.push(String::new());
+    writer.lines.push("\\maketitle".to_string());
+    if toc {
+        writer.lines.push("\\tableofcontents".to_string());
+    }
+    writer.lines.push(String::new());
+
+    writer.elements(document, &Scopes::default());
+    writer.lines.push("\\end{document}".to_string());
+    writer.lines
+}
+
+struct Writer {
+    lines:    Vec<String>,
+    // each exported headline, in order
+    sections: Vec<Section>,
+    // index of the next headline in 'sections'
+    section:  usize,
+    numbered: bool,
+}
+
+impl Writer {
+    /// Writes the exported elements of a document, whose headlines are
+    /// nested in the 'outer' ones
+    fn elements(&mut self, document: &Document, outer: &Scopes) {
+        let mut scopes = Scopes::enter_document(outer, document);
+        for element in document.exported() {
+            match element {
+                Element::Headline(h)  => {
+                    scopes.enter_headline(h);
+                    self.headline(h);
+                },
+                Element::Paragraph(p) => {
+                    let text = self.text(&p.lines);
+                    self.push_text(&text);
+                },
+                Element::List(l)  => self.list(l),
+                Element::Table(t) => self.table(t),
+                Element::Src(b) => if b.exported(&scopes) {
+                    self.verbatim(&b.lines);
+                },
+                Element::Example(b) => self.verbatim(&b.lines),
+                Element::Block(b) => {
+                    let backend = b.params.split_whitespace().next().unwrap_or("");
+                    match b.kind.as_str() {
+                        "export" if backend.eq_ignore_ascii_case("latex") => {
+                            self.lines.extend(b.lines.iter().cloned());
+                            self.lines.push(String::new());
+                        },
+                        "export" | "comment" => {},
+                        "verse" => {
+                            let lines: Vec<String> = b.lines.iter()
+                                .filter(|l| !l.trim().is_empty())
+                                .map(|l| format!("{}\\\\", self.inline(&markup::parse(l.trim()))))
+                                .collect();
+                            self.environment("verse", &lines);
+                        },
+                        // 'quote', 'center' and special blocks like
+                        // '#+BEGIN_note' become environments of that name
+                        kind => {
+                            let text = self.text(&b.lines);
+                            let lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+                            self.environment(kind, &lines);
+                        },
+                    }
+                },
+                Element::Keyword(k) if k.key == "LATEX" => {
+                    self.lines.push(k.value.clone());
+                },
+                Element::Include(i) => if let Some(content) = &i.content {
+                    self.elements(content, &scopes);
+                },
+                Element::Keyword(_) => {},
+            }
+        }
+    }
+
+    fn headline(&mut self, h: &Headline) {
+        let label = self.sections[self.section].anchor.clone();
+        self.section += 1;
+
+        let mut title = String::new();
+        if let Some(todo) = &h.todo {
+            title.push_str(&format!("\\textbf{{{}}} ", todo));
+        }
+        title.push_str(&self.inline(&markup::parse(&h.title)));
+        if !h.tags.is_empty() {
+            title.push_str(&format!("\\hfill{{}}\\textsc{{{}}}", escape(&h.tags.join(":"))));
+        }
+        let command = SECTIONS[(h.level - 1).min(SECTIONS.len() - 1)];
+        let star    = if self.numbered { "" } else { "*" };
+        self.lines.push(format!("\\{}{}{{{}}}", command, star, title));
+        self.lines.push(format!("\\label{{{}}}", label));
+    }
+
+    /// Nested itemize, enumerate and description environments of a list
+    fn list(&mut self, list: &List) {
+        // (<indentation>, <environment>) of the open lists
+        let mut open: Vec<(usize, &str)> = Vec::new();
+        for item in &list.items {
+            let env = if item.tag.is_some() {
+                "description"
+            } else if item.ordered() {
+                "enumerate"
+            } else {
+                "itemize"
+            };
+            while let Some(&(indent, open_env)) = open.last() {
+                if indent > item.indent || (indent == item.indent && open_env != env) {
+                    self.lines.push(format!("\\end{{{}}}", open_env));
+                    open.pop();
+                } else {
+                    break;
+                }
+            }
+            if open.last().is_none_or(|(indent, _)| *indent < item.indent) {
+                self.lines.push(format!("\\begin{{{}}}", env));
+                open.push((item.indent, env));
+            }
+
+            let label = match (&item.tag, item.checkbox) {
+                (Some(tag), _)  => format!("[{{{}}}]", self.inline(&markup::parse(tag))),
+                (None, Some(' ')) => "[$\\square$]".to_string(),
+                (None, Some('-')) => "[$\\boxminus$]".to_string(),
+                (None, Some(_))   => "[$\\boxtimes$]".to_string(),
+                (None, None)      => String::new(),
+            };
+            let text = self.text(&item.lines);
+            let mut lines = text.lines();
+            self.lines.push(format!("\\item{} {}", label, lines.next().unwrap_or("")).trim_end().to_string());
+            self.lines.extend(lines.map(|l| l.to_string()));
+        }
+        while let Some((_, env)) = open.pop() {
+            self.lines.push(format!("\\end{{{}}}", env));
+        }
+        self.lines.push(String::new());
+    }
+
+    /// Table with right-aligned columns of mostly numbers, as in org
+    fn table(&mut self, table: &Table) {
+        let columns = table.columns();
+        let align: String = table.numeric_columns(0)
+                                 .iter()
+                                 .map(|&numeric| if numeric { 'r' } else { 'l' })
+                                 .collect();
+
+        self.lines.push("\\begin{center}".to_string());
+        self.lines.push(format!("\\begin{{tabular}}{{{}}}", align));
+        for row in &table.rows {
+            match row {
+                TableRow::Cells(cells) => {
+                    let cells: Vec<String> = (0..columns).map(|c| match cells.get(c) {
+                        Some(cell) => self.inline(&markup::parse(cell.trim())),
+                        None       => String::new(),
+                    }).collect();
+                    self.lines.push(format!("{}\\\\", cells.join(" & ")));
+                },
+                TableRow::Rule => self.lines.push("\\hline".to_string()),
+            }
+        }
+        self.lines.push("\\end{tabular}".to_string());
+        self.lines.push("\\end{center}".to_string());
+        self.lines.push(String::new());
+    }
+
+    fn verbatim(&mut self, lines: &[String]) {
+        self.lines.push("\\begin{verbatim}".to_string());
+        self.lines.extend(lines.iter().map(|l| expand_tabs(l, 4)));
+        self.lines.push("\\end{verbatim}".to_string());
+        self.lines.push(String::new());
+    }
+
+    fn environment(&mut self, name: &str, lines: &[String]) {
+        self.lines.push(format!("\\begin{{{}}}", name));
+        self.lines.extend(lines.iter().cloned());
+        self.lines.push(format!("\\end{{{}}}", name));
+        self.lines.push(String::new());
+    }
+
+    /// Paragraphs of text, separated by empty lines
+    fn text(&self, lines: &[String]) -> String {
+        markup::paragraphs(lines).iter()
+                                 .map(|p| self.inline(&markup::parse(p)))
+                                 .collect::<Vec<String>>()
+                                 .join("\n\n")
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.lines.extend(text.lines().map(|l| l.to_string()));
+        self.lines.push(String::new());
+    }
+
+    fn inline(&self, inlines: &[Inline]) -> String {
+        let mut latex = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(s)      => latex.push_str(&escape(s)),
+                Inline::Bold(c)      => latex.push_str(&format!("\\textbf{{{}}}", self.inline(c))),
+                Inline::Italic(c)    => latex.push_str(&format!("\\emph{{{}}}", self.inline(c))),
+                Inline::Underline(c) => latex.push_str(&format!("\\uline{{{}}}", self.inline(c))),
+                Inline::Strike(c)    => latex.push_str(&format!("\\sout{{{}}}", self.inline(c))),
+                Inline::Verbatim(s) |
+                Inline::Code(s)      => latex.push_str(&format!("\\texttt{{{}}}", escape(s))),
+                Inline::Latex(s)     => latex.push_str(s),
+                Inline::Link { target, description } => {
+                    latex.push_str(&self.link(target, description));
+                },
+            }
+        }
+        latex
+    }
+
+    fn link(&self, target: &str, description: &[Inline]) -> String {
+        let text = if description.is_empty() {
+            escape(target)
+        } else {
+            self.inline(description)
+        };
+        match markup::resolve_link(target, description, &self.sections, &IMAGES) {
+            Link::Url(url) if description.is_empty() => format!("\\url{{{}}}", escape_url(url)),
+            Link::Url(path) |
+            Link::File(path) => format!("\\href{{{}}}{{{}}}", escape_url(path), text),
+            Link::Section(section) if description.is_empty() => {
+                format!("\\ref{{{}}}", section.anchor)
+            },
+            Link::Section(section) => format!("\\hyperref[{}]{{{}}}", section.anchor, text),
+            Link::Image(path) => format!("\\includegraphics[width=.9\\linewidth]{{{}}}", path),
+            Link::Other       => text,
+        }
+    }
+}
+
+/// Escapes characters with a special meaning in LaTeX
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~'  => escaped.push_str("\\textasciitilde{}"),
+            '^'  => escaped.push_str("\\^{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c    => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes the characters of a URL that '\href' and '\url' can't take as is
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::new();
+    for c in url.chars() {
+        if "#%&{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,14 @@
+pub mod error;
+pub mod file;
+pub mod header;
+pub mod document;
+pub mod export;
+pub mod backend;
+mod mapping;
+mod diff;
+mod json;
+mod markup;
+mod latex;
+mod highlight;
+mod html;
+mod markdown;
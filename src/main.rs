@@ -1,63 +1,104 @@
 
+extern crate exorg;
+
 // for reading command line arguments
 use std::env;
+use std::process;
 
-mod error;
-mod file;
-mod export;
-
-use export::Exporter;
+use exorg::export::{Exporter, Options};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let (format, in_filename, out_filename, block) = match read_cli_args(args) {
+    let (format, in_filename, mapped_format, options) = match read_cli_args(args) {
         None    => return,
         Some(s) => s,
     };
 
-
+    // detangling reads a tangled file instead of an org file
+    if format == "detangle" {
+        if let Err(e) = Exporter::detangle(&in_filename) {
+            println!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
     let exporter = match Exporter::from_file(&in_filename) {
         Err(e) => {
             println!("Error: {}", e);
-            return;
+            // 'check' exits with 1 for outdated files only
+            process::exit(if format == "check" { 2 } else { 1 });
         },
         Ok(ex) => ex,
     };
-    match exporter.export(&format, &block, &out_filename) {
-        Err(e) => {
+    // checking signals outdated files by the exit status
+    if format == "check" {
+        match exporter.check(&options) {
+            Ok(true)  => {},
+            Ok(false) => process::exit(1),
+            Err(e)    => {
+                println!("Error: {}", e);
+                process::exit(2);
+            },
+        }
+        return;
+    }
+    // mapping errors needs the files written by extracting the given format
+    if format == "map-errors" {
+        let mapped_format = mapped_format.as_deref().unwrap_or(".");
+        if let Err(e) = exporter.map_errors(mapped_format, &options) {
             println!("Error: {}", e);
-            return;
-        },
-        _      => {},
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(e) = exporter.export(&format, &options) {
+        println!("Error: {}", e);
+        process::exit(1);
     }
 }
 
-fn read_cli_args(args: Vec<String>) -> Option<(String, String, Option<String>,
-                                                            Option<String>)> {
+fn read_cli_args(args: Vec<String>) -> Option<(String, String, Option<String>, Options)> {
     let mut in_filename = String::new();
     let mut format      = None;
-    let mut out_opt     = None;
-    let mut block_opt   = None;
+    // format whose extracted files 'map-errors' looks at
+    let mut mapped_format = None;
+    let mut options     = Options::default();
 
     let mut wait_block  = false;
     let mut wait_out    = false;
+    let mut wait_manifest = false;
+    let mut wait_output_dir = false;
 
-    for i in 1..args.len() {
-        match args[i].as_str() {
+    for arg in args.iter().skip(1) {
+        match arg.as_str() {
             "-b" => wait_block = true,
             "-o" => wait_out   = true,
+            "--source-map" => options.source_map = true,
+            "--dry-run"    => options.dry_run    = true,
+            "--backend"    => options.backend    = true,
+            "--manifest"   => wait_manifest      = true,
+            "--output-dir" => wait_output_dir    = true,
             _    => {
-                if wait_block {
-                    block_opt = Some(args[i].clone());
+                if wait_output_dir {
+                    options.output_dir = Some(arg.clone());
+                    wait_output_dir = false;
+                } else if wait_manifest {
+                    options.manifest = Some(arg.clone());
+                    wait_manifest = false;
+                } else if wait_block {
+                    options.block = Some(arg.clone());
                     wait_block = false;
                 } else if wait_out {
-                    out_opt = Some(args[i].clone());
+                    options.out_filename = Some(arg.clone());
                     wait_out = false;
                 } else if format.is_none() {
-                    format = Some(args[i].clone());
+                    format = Some(arg.clone());
+                } else if format.as_deref() == Some("map-errors") && !in_filename.is_empty() {
+                    mapped_format = Some(arg.clone());
                 } else {
-                    in_filename = args[i].clone();
+                    in_filename = arg.clone();
                 }
             },
         }
@@ -68,12 +109,20 @@ fn read_cli_args(args: Vec<String>) -> Option<(String, String, Option<String>,
         return None;
     }
 
-    Some((format.unwrap(), in_filename, out_opt, block_opt))
+    Some((format.unwrap(), in_filename, mapped_format, options))
 }
 
 fn print_help() {
     let msg = r#"
 usage:  exorg <format> <file> [-b <block name>] [-o <output file>]
+                                [--source-map] [--dry-run]
+                                [--manifest <manifest file>]
+                                [--output-dir <directory>] [--backend]
+        exorg detangle <tangled file>
+        exorg map-errors <file> [<format>] [-b <block name>]
+                                [-o <output file>]
+                                [--output-dir <directory>] < compiler-output
+        exorg check <file>
         exorg [--help]
     
 arguments:
@@ -83,20 +132,59 @@ arguments:
                         - pdf-minted    (much nicer-looking source code)
                         - pdf-pandoc    (pdf conversion via pandoc with
                                          --table-of-contents flag enabled)
+                        - pdf-native    (LaTeX written by exorg, no Emacs
+                                         needed)
+                        - html          (standalone page with table of
+                                         contents)
+                        - md, gfm       (Markdown, GitHub-flavored Markdown)
                         - jupyter
                         - .             extract all blocks with ':tangle <file>'
                                         parameter to the given paths.
                         - custom format, defined in .org file via
                                 '#+SRC_LANG: <language name> <file suffix>'
                            e.g. '#+SRC_LANG: rust rs'
+                        - any other format handled by a program
+                          'exorg-<format>' on PATH, which gets the
+                          document as JSON on stdin
+
+    detangle        write changes in a file tangled with ':comments link'
+                    back into the code blocks of the org file(s)
+
+    map-errors      read compiler or test output from stdin and print it with
+                    locations in extracted files replaced by the
+                    corresponding locations in the org file(s). The files
+                    are the ones extracting <format>, '.' by default, with
+                    the same -b, -o and --output-dir would write.
+
+    check           extract all blocks with ':tangle <file>' in memory and
+                    print a diff for each file on disk that is out of date.
+                    Exits with status 1 if any file differs and 2 on errors.
 
     <block name>    name of a specific code block to be extracted. If this block
                     depends on other blocks, those will be included as well.
                     (set via '#+NAME: <name>' before src block)
 
     <output file>   name of the exported src file. Default is name of .org input
-                    file with the suffix replaced. This argument disables
-                    automatic file suffix.
+                    file with the suffix replaced, next to the .org file.
+                    This argument disables automatic file suffix.
+
+    --source-map    write a JSON file '<output file>.map.json' next to each
+                    extracted file, mapping its lines to the lines of the
+                    org file(s) they were taken from.
+
+    --output-dir    write extracted files into the given directory instead of
+                    the .org file's one. Files outside of it are placed below
+                    the directory with their absolute path.
+
+    --dry-run       only print which blocks would be written into each file
+                    and how many lines it would have, without writing anything.
+
+    --manifest      write a JSON file listing the blocks(name, source file and
+                    line) and line count of each extracted file. With
+                    --dry-run it is printed instead.
+
+    --backend       export with the backend of <format>, e.g. to get an HTML
+                    page of a document declaring '#+SRC_LANG: html html'.
     "#; 
     println!("{}", msg);
 }
@@ -0,0 +1,101 @@
+
+/// Rewrites file locations in a line of compiler or test output. Recognized
+/// are Python's 'File "foo.py", line 12' and the 'foo.rs:12:5' / 'foo.c:12:'
+/// form used by rustc, gcc and most other tools.
+///
+/// 'map' receives path, line and column(if any) of every location found and
+/// returns the replacement, or None to keep the location as it is.
+pub fn rewrite_locations<F>(line: &str, map: F) -> String
+    where F: Fn(&str, usize, Option<usize>) -> Option<(String, usize, Option<usize>)> {
+
+    // (<start>, <end>, <replacement>) of each rewritten location
+    let mut replacements: Vec<(usize, usize, String)> = Vec::new();
+
+    // Python tracebacks
+    let mut pos = 0;
+    while let Some(i) = line[pos..].find("File \"") {
+        let start = pos + i + 6;
+        pos = start;
+        let path_len = match line[start..].find('"') {
+            Some(l) => l,
+            None    => break,
+        };
+        let path = &line[start..start+path_len];
+        let rest = &line[start+path_len..];
+        if !rest.starts_with("\", line ") {
+            continue;
+        }
+        let num_start = start + path_len + 8;
+        let num_len = digits(&line[num_start..]);
+        if num_len == 0 {
+            continue;
+        }
+        let num = line[num_start..num_start+num_len].parse().unwrap_or(0);
+        if let Some((p, l, _)) = map(path, num, None) {
+            replacements.push((start, num_start+num_len,
+                               format!("{}\", line {}", p, l)));
+        }
+        pos = num_start + num_len;
+    }
+
+    // path:line[:column]
+    let bytes = line.as_bytes();
+    for i in 0..bytes.len() {
+        if bytes[i] != b':' || digits(&line[i+1..]) == 0 {
+            continue;
+        }
+        let start = match line[..i].char_indices().rev().find(|(_, c)| is_delimiter(*c)) {
+            Some((d, c)) => d + c.len_utf8(),
+            None         => 0,
+        };
+        if start == i || replacements.iter().any(|r| r.0 <= i && i < r.1) {
+            continue;
+        }
+        let path = &line[start..i];
+
+        let line_len = digits(&line[i+1..]);
+        let num: usize = line[i+1..i+1+line_len].parse().unwrap_or(0);
+        let mut end = i + 1 + line_len;
+
+        let col_len = if line[end..].starts_with(':') { digits(&line[end+1..]) } else { 0 };
+        let col = if col_len > 0 {
+            let c = line[end+1..end+1+col_len].parse().ok();
+            end += 1 + col_len;
+            c
+        } else {
+            None
+        };
+
+        if let Some((p, l, c)) = map(path, num, col) {
+            let text = match c {
+                Some(c) => format!("{}:{}:{}", p, l, c),
+                None    => format!("{}:{}", p, l),
+            };
+            replacements.push((start, end, text));
+        }
+    }
+
+    replacements.sort_by_key(|r| r.0);
+    let mut result = String::new();
+    let mut last = 0;
+    for (start, end, text) in replacements {
+        if start < last {
+            continue;
+        }
+        result.push_str(&line[last..start]);
+        result.push_str(&text);
+        last = end;
+    }
+    result.push_str(&line[last..]);
+    result
+}
+
+/// Number of ASCII digits at the start of the string
+fn digits(s: &str) -> usize {
+    s.bytes().take_while(|b| b.is_ascii_digit()).count()
+}
+
+/// Characters which can't be part of a path in compiler output
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "\"'()[]<>,;=`".contains(c)
+}
@@ -0,0 +1,384 @@
+use document::{Document, Element, Headline, ListItem, Scopes, Table};
+use markup::{self, Inline, Link, Section};
+
+/// Extensions of the images Markdown viewers show
+const IMAGES: [&str; 6] = ["png", "jpg", "jpeg", "gif", "svg", "webp"];
+
+/// Variant of Markdown to write
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Flavor {
+    // CommonMark plus pipe tables
+    Markdown,
+    // GitHub-flavored Markdown, with strikethrough and task lists
+    Gfm,
+}
+
+/// Converts an org document into Markdown, keeping the levels of its
+/// headlines
+pub fn to_markdown(document: &Document, flavor: Flavor) -> Vec<String> {
+    // anchors are the custom ids or the ones GitHub derives from the text
+    // of the headlines, numbering repeated ones
+    let mut slugs: Vec<String> = Vec::new();
+    let sections = markup::sections(document, &mut |h, _| match h.custom_id() {
+        Some(id) => id.to_string(),
+        None     => {
+            let text = markup::plain_text(&markup::parse(&h.title));
+            let text = match &h.todo {
+                Some(todo) => format!("{} {}", todo, text),
+                None       => text,
+            };
+            let slug  = slug(&text);
+            let count = slugs.iter().filter(|s| **s == slug).count();
+            slugs.push(slug.clone());
+            if count == 0 { slug } else { format!("{}-{}", slug, count) }
+        },
+    });
+    let mut writer = Writer {
+        lines:    Vec::new(),
+        sections,
+        section:  0,
+        flavor,
+    };
+    writer.elements(document, &Scopes::default());
+    while writer.lines.last().is_some_and(|l| l.is_empty()) {
+        writer.lines.pop();
+    }
+    writer.lines
+}
+
+struct Writer {
+    lines:    Vec<String>,
+    // each exported headline, in order
+    sections: Vec<Section>,
+    // index of the next headline in 'sections'
+    section:  usize,
+    flavor:   Flavor,
+}
+
+impl Writer {
+    /// Writes the exported elements of a document, whose headlines are
+    /// nested in the 'outer' ones
+    fn elements(&mut self, document: &Document, outer: &Scopes) {
+        let mut scopes = Scopes::enter_document(outer, document);
+        for element in document.exported() {
+            match element {
+                Element::Headline(h)  => {
+                    scopes.enter_headline(h);
+                    self.headline(h);
+                },
+                Element::Paragraph(p) => {
+                    let text = self.text(&p.lines);
+                    self.push_block(text);
+                },
+                Element::List(l)  => {
+                    let lines = self.list(&l.items, 0);
+                    self.push_block(lines);
+                },
+                Element::Table(t) => self.table(t),
+                Element::Src(b) => if b.exported(&scopes) {
+                    self.fenced(&b.lang, &b.lines);
+                },
+                Element::Example(b) => self.fenced("", &b.lines),
+                Element::Block(b) => {
+                    let backend = b.params.split_whitespace().next().unwrap_or("").to_lowercase();
+                    match b.kind.as_str() {
+                        // Markdown may contain HTML
+                        "export" if ["md", "markdown", "gfm", "html"].contains(&backend.as_str()) => {
+                            self.push_block(b.lines.clone());
+                        },
+                        "export" | "comment" => {},
+                        "quote" => {
+                            let lines = self.text(&b.lines)
+                                            .iter()
+                                            .map(|l| if l.is_empty() {
+                                                ">".to_string()
+                                            } else {
+                                                format!("> {}", l)
+                                            })
+                                            .collect();
+                            self.push_block(lines);
+                        },
+                        "verse" => {
+                            let lines: Vec<String> = b.lines.iter()
+                                .filter(|l| !l.trim().is_empty())
+                                .map(|l| self.inline(&markup::parse(l.trim())))
+                                .collect();
+                            // lines end with a hard line break
+                            let last = lines.len().saturating_sub(1);
+                            let lines = lines.into_iter()
+                                             .enumerate()
+                                             .map(|(i, l)| if i < last { format!("{}\\", l) } else { l })
+                                             .collect();
+                            self.push_block(lines);
+                        },
+                        _ => {
+                            let text = self.text(&b.lines);
+                            self.push_block(text);
+                        },
+                    }
+                },
+                Element::Keyword(k) if k.key == "MD" || k.key == "HTML" => {
+                    self.push_block(vec![k.value.clone()]);
+                },
+                Element::Include(i) => if let Some(content) = &i.content {
+                    self.elements(content, &scopes);
+                },
+                Element::Keyword(_) => {},
+            }
+        }
+    }
+
+    fn push_block(&mut self, mut lines: Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        self.lines.append(&mut lines);
+        self.lines.push(String::new());
+    }
+
+    fn headline(&mut self, h: &Headline) {
+        let section = self.sections[self.section].clone();
+        self.section += 1;
+
+        // custom ids need an anchor of their own
+        if section.custom_id.is_some() {
+            self.lines.push(format!("<a id=\"{}\"></a>", section.anchor));
+        }
+        let mut heading = "#".repeat(h.level.min(6));
+        heading.push(' ');
+        if let Some(todo) = &h.todo {
+            heading.push_str(todo);
+            heading.push(' ');
+        }
+        heading.push_str(&self.inline(&markup::parse(&h.title)));
+        self.push_block(vec![heading]);
+    }
+
+    /// Lines of a list, the items on the level of the first one being
+    /// indented by 'indent' columns and nested ones below their parents'
+    /// text
+    fn list(&self, items: &[ListItem], indent: usize) -> Vec<String> {
+        let level = items[0].indent;
+        let mut lines = Vec::new();
+        let mut number = 0;
+        let mut i = 0;
+        while i < items.len() {
+            let item = &items[i];
+            let children = items[i+1..].iter()
+                                       .take_while(|c| c.indent > item.indent.max(level))
+                                       .count();
+            let marker = if item.ordered() {
+                number = item.bullet.trim_end_matches(['.', ')'])
+                                    .parse()
+                                    .unwrap_or(number + 1);
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+            let mut first = marker.clone();
+            if let Some(tag) = &item.tag {
+                first.push_str(&format!("**{}**: ", self.inline(&markup::parse(tag))));
+            } else if let Some(state) = item.checkbox {
+                first.push_str(match (self.flavor, state) {
+                    (Flavor::Gfm, ' ')      => "[ ] ",
+                    (Flavor::Gfm, _)        => "[x] ",
+                    (Flavor::Markdown, ' ') => "\\[ \\] ",
+                    (Flavor::Markdown, '-') => "\\[-\\] ",
+                    (Flavor::Markdown, _)   => "\\[X\\] ",
+                });
+            }
+
+            let pad = " ".repeat(indent);
+            let continuation = " ".repeat(indent + marker.len());
+            for (n, line) in self.text(&item.lines).iter().enumerate() {
+                lines.push(if n == 0 {
+                    format!("{}{}{}", pad, first, line).trim_end().to_string()
+                } else if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}{}", continuation, line)
+                });
+            }
+            if children > 0 {
+                lines.append(&mut self.list(&items[i+1..i+1+children], indent + marker.len()));
+            }
+            i += 1 + children;
+        }
+        lines
+    }
+
+    /// Pipe table, the first row being its header as Markdown requires one
+    fn table(&mut self, table: &Table) {
+        let rows = table.cell_rows();
+        if rows.is_empty() {
+            return;
+        }
+        let columns = table.columns();
+        let align: Vec<&str> = table.numeric_columns(1)
+                                    .iter()
+                                    .map(|&numeric| if numeric { "---:" } else { "---" })
+                                    .collect();
+
+        let row = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+        let mut lines = Vec::new();
+        for (i, cells) in rows.iter().enumerate() {
+            lines.push(row((0..columns).map(|c| match cells.get(c) {
+                Some(cell) => self.inline(&markup::parse(cell.trim())).replace('|', "\\|"),
+                None       => String::new(),
+            }).collect()));
+            if i == 0 {
+                lines.push(row(align.iter().map(|a| a.to_string()).collect()));
+            }
+        }
+        self.push_block(lines);
+    }
+
+    /// Fenced code block, the fence being longer than any run of backticks
+    /// in the code
+    fn fenced(&mut self, lang: &str, code: &[String]) {
+        let longest = code.iter()
+                          .map(|l| l.trim_start().chars().take_while(|c| *c == '`').count())
+                          .max()
+                          .unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+        let mut lines = vec![format!("{}{}", fence, lang)];
+        lines.extend(code.iter().cloned());
+        lines.push(fence);
+        self.push_block(lines);
+    }
+
+    /// Lines of text, paragraphs being separated by an empty line
+    fn text(&self, lines: &[String]) -> Vec<String> {
+        let mut result = Vec::new();
+        for paragraph in markup::paragraphs(lines) {
+            if !result.is_empty() {
+                result.push(String::new());
+            }
+            let markdown = self.inline(&markup::parse(&paragraph));
+            result.extend(markdown.lines().map(|l| {
+                // keep lines from starting a heading or quote
+                if l.starts_with(['#', '>']) { format!("\\{}", l) } else { l.to_string() }
+            }));
+        }
+        result
+    }
+
+    fn inline(&self, inlines: &[Inline]) -> String {
+        let mut md = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(s)      => md.push_str(&escape(s)),
+                Inline::Bold(c)      => md.push_str(&format!("**{}**", self.inline(c))),
+                Inline::Italic(c)    => md.push_str(&format!("*{}*", self.inline(c))),
+                Inline::Underline(c) => md.push_str(&format!("<u>{}</u>", self.inline(c))),
+                Inline::Strike(c)    => md.push_str(&match self.flavor {
+                    Flavor::Gfm      => format!("~~{}~~", self.inline(c)),
+                    Flavor::Markdown => format!("<del>{}</del>", self.inline(c)),
+                }),
+                Inline::Verbatim(s) |
+                Inline::Code(s)      => md.push_str(&code_span(s)),
+                Inline::Latex(s)     => md.push_str(&latex(s)),
+                Inline::Link { target, description } => {
+                    md.push_str(&self.link(target, description));
+                },
+            }
+        }
+        md
+    }
+
+    fn link(&self, target: &str, description: &[Inline]) -> String {
+        let text = if description.is_empty() {
+            escape(target)
+        } else {
+            self.inline(description)
+        };
+        match markup::resolve_link(target, description, &self.sections, &IMAGES) {
+            Link::Url(url) if description.is_empty() => format!("<{}>", url),
+            Link::Url(url) => format!("[{}]({})", text, url),
+            Link::Section(section) => {
+                let text = if description.is_empty() {
+                    escape(&markup::plain_text(&markup::parse(&section.title)))
+                } else {
+                    text
+                };
+                format!("[{}](#{})", text, section.anchor)
+            },
+            Link::Image(path) => format!("![{}]({})", escape(path), url(path)),
+            Link::File(path)  => {
+                // links to org files lead to their exported Markdown
+                let href = match path.strip_suffix(".org") {
+                    Some(stem) if !path.contains("://") => format!("{}.md", stem),
+                    _                                   => path.to_string(),
+                };
+                format!("[{}]({})", text, url(&href))
+            },
+            Link::Other => text,
+        }
+    }
+}
+
+/// Anchor GitHub generates for a heading
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' '                                        => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _                                          => None,
+        })
+        .collect()
+}
+
+/// Code span, using as many backticks as needed to enclose the code
+fn code_span(code: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in code.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let ticks = "`".repeat(longest + 1);
+    if longest > 0 || code.starts_with(' ') || code.ends_with(' ') {
+        format!("{} {} {}", ticks, code, ticks)
+    } else {
+        format!("{}{}{}", ticks, code, ticks)
+    }
+}
+
+/// LaTeX fragment in the math syntax GitHub renders
+fn latex(fragment: &str) -> String {
+    if fragment == "\\\\" {
+        // hard line break
+        return "\\".to_string();
+    }
+    if let Some(math) = fragment.strip_prefix("\\(").and_then(|f| f.strip_suffix("\\)")) {
+        return format!("${}$", math);
+    }
+    if let Some(math) = fragment.strip_prefix("\\[").and_then(|f| f.strip_suffix("\\]")) {
+        return format!("$${}$$", math);
+    }
+    fragment.to_string()
+}
+
+/// Escapes characters that would otherwise be taken as Markdown. Underscores
+/// within words are left alone, as they don't emphasize anything there.
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let within_word = i > 0 && chars[i-1].is_alphanumeric() &&
+                          chars.get(i+1).is_some_and(|n| n.is_alphanumeric());
+        match c {
+            '\\' | '`' | '*' | '[' | ']' | '<' => escaped.push('\\'),
+            '_' if !within_word                => escaped.push('\\'),
+            _                                  => {},
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Link destination with the characters Markdown can't take encoded
+fn url(path: &str) -> String {
+    path.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use document::{Document, Element, Headline};
+
+/// Markup inside org text like '*bold*' or '[[url][description]]'
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Underline(Vec<Inline>),
+    Strike(Vec<Inline>),
+    // '=verbatim=' and '~code~', taken literally
+    Verbatim(String),
+    Code(String),
+    // '[[target][description]]', '[[target]]' or a plain URL, the
+    // description being empty if there is none
+    Link { target: String, description: Vec<Inline> },
+    // '$x$', '\(x\)', '\[x\]', a command like '\alpha{}' or a line break '\\'
+    Latex(String),
+}
+
+/// Parses org text, which may span several lines, into its markup
+pub fn parse(text: &str) -> Vec<Inline> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = Vec::new();
+    let mut plain  = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let found = link(&chars, i)
+            .or_else(|| plain_link(&chars, i))
+            .or_else(|| emphasis(&chars, i))
+            .or_else(|| latex(&chars, i));
+        match found {
+            Some((inline, end)) => {
+                if !plain.is_empty() {
+                    result.push(Inline::Text(plain.split_off(0)));
+                }
+                result.push(inline);
+                i = end;
+            },
+            None => {
+                plain.push(chars[i]);
+                i += 1;
+            },
+        }
+    }
+    if !plain.is_empty() {
+        result.push(Inline::Text(plain));
+    }
+    result
+}
+
+/// Text of some markup without any formatting
+pub fn plain_text(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(s) | Inline::Verbatim(s) |
+            Inline::Code(s) | Inline::Latex(s)    => text.push_str(s),
+            Inline::Bold(c) | Inline::Italic(c) |
+            Inline::Underline(c) | Inline::Strike(c) => text.push_str(&plain_text(c)),
+            Inline::Link { target, description } => if description.is_empty() {
+                text.push_str(target.strip_prefix('*').unwrap_or(target));
+            } else {
+                text.push_str(&plain_text(description));
+            },
+        }
+    }
+    text
+}
+
+/// Text of each paragraph of some lines, which are separated by blank lines.
+/// The lines of a paragraph are trimmed and joined by newlines.
+pub fn paragraphs(lines: &[String]) -> Vec<String> {
+    lines.split(|l| l.trim().is_empty())
+         .filter(|p| !p.is_empty())
+         .map(|p| p.iter().map(|l| l.trim()).collect::<Vec<&str>>().join("\n"))
+         .collect()
+}
+
+/// Exported headline as writers number it and link to it
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub level:     usize,
+    // like '2.1'
+    pub number:    String,
+    pub title:     String,
+    pub custom_id: Option<String>,
+    // label or anchor links to the headline point to
+    pub anchor:    String,
+}
+
+/// Numbers the exported headlines of a document and its included files.
+/// 'anchor' is given each headline along with its number.
+pub fn sections<F>(document: &Document, anchor: &mut F) -> Vec<Section>
+    where F: FnMut(&Headline, &str) -> String {
+    let mut sections = Vec::new();
+    collect_sections(document, anchor, &mut Vec::new(), &mut sections);
+    sections
+}
+
+/// 'counters' holds the numbers of the enclosing headlines
+fn collect_sections<F>(document: &Document, anchor: &mut F,
+                       counters: &mut Vec<usize>, sections: &mut Vec<Section>)
+    where F: FnMut(&Headline, &str) -> String {
+    for element in document.exported() {
+        match element {
+            Element::Headline(h) => {
+                counters.resize(h.level, 0);
+                counters[h.level-1] += 1;
+                let number: Vec<String> = counters.iter().map(|n| n.to_string()).collect();
+                let number = number.join(".");
+                sections.push(Section {
+                    level:     h.level,
+                    anchor:    anchor(h, &number),
+                    number,
+                    title:     h.title.clone(),
+                    custom_id: h.custom_id().map(|id| id.to_string()),
+                });
+            },
+            Element::Include(i) => if let Some(content) = &i.content {
+                collect_sections(content, anchor, counters, sections);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// What a link leads to
+#[derive(Debug)]
+pub enum Link<'a> {
+    // web or mail address
+    Url(&'a str),
+    // headline given by custom id like '#intro' or title like '*Intro'
+    Section(&'a Section),
+    // image to show instead of a link, the path without 'file:'
+    Image(&'a str),
+    // other file, the path without 'file:'
+    File(&'a str),
+    // anything else, e.g. the name of a block, shown as plain text
+    Other,
+}
+
+/// Finds out what the target of a link refers to. Links without description
+/// to files with one of the given extensions are images.
+pub fn resolve_link<'a>(target: &'a str, description: &[Inline],
+                        sections: &'a [Section], images: &[&str]) -> Link<'a> {
+    if ["http:", "https:", "ftp:", "mailto:"].iter().any(|p| target.starts_with(p)) {
+        return Link::Url(target);
+    }
+    let section = match target.strip_prefix('#') {
+        Some(id) => sections.iter().find(|s| s.custom_id.as_deref() == Some(id)),
+        None     => {
+            let title = target.strip_prefix('*').unwrap_or(target);
+            sections.iter().find(|s| s.title == title)
+        },
+    };
+    if let Some(section) = section {
+        return Link::Section(section);
+    }
+
+    let path = target.strip_prefix("file:").unwrap_or(target);
+    if description.is_empty() && is_image(path, images) {
+        Link::Image(path)
+    } else if target.starts_with("file:") || path.contains(['/', '.', ':']) {
+        Link::File(path)
+    } else {
+        Link::Other
+    }
+}
+
+/// Whether a path ends with one of the given extensions, ignoring case
+pub fn is_image(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path).extension()
+                   .map(|e| e.to_string_lossy().to_lowercase())
+                   .is_some_and(|e| extensions.contains(&e.as_str()))
+}
+
+fn starts_with(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars().enumerate().all(|(n, c)| chars.get(i + n) == Some(&c))
+}
+
+/// Index of the next occurence of 's' at or after 'i'
+fn find(chars: &[char], i: usize, s: &str) -> Option<usize> {
+    (i..chars.len()).find(|&j| starts_with(chars, j, s))
+}
+
+/// '[[target][description]]' or '[[target]]' starting at 'i', along with the
+/// index following it
+fn link(chars: &[char], i: usize) -> Option<(Inline, usize)> {
+    if !starts_with(chars, i, "[[") {
+        return None;
+    }
+    let end = find(chars, i + 2, "]]")?;
+    let inner: String = chars[i+2..end].iter().collect();
+    let (target, description) = match inner.split_once("][") {
+        Some((t, d)) => (t.to_string(), parse(d)),
+        None         => (inner, Vec::new()),
+    };
+    Some((Inline::Link { target, description }, end + 2))
+}
+
+/// URL like 'https://example.com' within text
+fn plain_link(chars: &[char], i: usize) -> Option<(Inline, usize)> {
+    if !["http://", "https://", "mailto:"].iter().any(|s| starts_with(chars, i, s)) ||
+       (i > 0 && chars[i-1].is_alphanumeric()) {
+        return None;
+    }
+    let mut end = (i..chars.len()).find(|&j| chars[j].is_whitespace() ||
+                                             "<>\"[]".contains(chars[j]))
+                                  .unwrap_or(chars.len());
+    // punctuation at the end belongs to the sentence
+    while end > i && ".,;:!?)'".contains(chars[end-1]) {
+        end -= 1;
+    }
+    let target: String = chars[i..end].iter().collect();
+    Some((Inline::Link { target, description: Vec::new() }, end))
+}
+
+/// Emphasis like '*bold*' starting at 'i', following org's rules for the
+/// characters around the markers
+fn emphasis(chars: &[char], i: usize) -> Option<(Inline, usize)> {
+    let marker = chars[i];
+    if !"*/_=~+".contains(marker) ||
+       (i > 0 && !chars[i-1].is_whitespace() && !"-({'\"".contains(chars[i-1])) ||
+       chars.get(i+1).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    let end = (i+2..chars.len()).find(|&j| {
+        chars[j] == marker && !chars[j-1].is_whitespace() &&
+        chars.get(j+1).is_none_or(|c| c.is_whitespace() ||
+                                      "-.,;:!?')}[\"\\".contains(*c))
+    })?;
+    let content: String = chars[i+1..end].iter().collect();
+    let inline = match marker {
+        '*' => Inline::Bold(parse(&content)),
+        '/' => Inline::Italic(parse(&content)),
+        '_' => Inline::Underline(parse(&content)),
+        '+' => Inline::Strike(parse(&content)),
+        '=' => Inline::Verbatim(content),
+        _   => Inline::Code(content),
+    };
+    Some((inline, end + 1))
+}
+
+/// LaTeX fragment starting at 'i'
+fn latex(chars: &[char], i: usize) -> Option<(Inline, usize)> {
+    let fragment = |end: usize| Some((Inline::Latex(chars[i..end].iter().collect()), end));
+    match chars[i] {
+        '\\' => match chars.get(i+1)? {
+            '\\' => fragment(i + 2),
+            '('  => fragment(find(chars, i + 2, "\\)")? + 2),
+            '['  => fragment(find(chars, i + 2, "\\]")? + 2),
+            c if c.is_ascii_alphabetic() => {
+                let mut end = i + 1;
+                while end < chars.len() && chars[end].is_ascii_alphabetic() {
+                    end += 1;
+                }
+                // arguments of a command
+                while chars.get(end) == Some(&'{') {
+                    let mut depth = 0;
+                    let close = (end..chars.len()).find(|&j| {
+                        match chars[j] {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _   => {},
+                        }
+                        depth == 0
+                    })?;
+                    end = close + 1;
+                }
+                fragment(end)
+            },
+            _ => None,
+        },
+        '$' => {
+            if starts_with(chars, i, "$$") {
+                return fragment(find(chars, i + 2, "$$")? + 2);
+            }
+            // as in org, '$' only starts math if it isn't followed by
+            // whitespace and isn't part of an amount like '$5 and $10'
+            let next = *chars.get(i+1)?;
+            if next.is_whitespace() || ".,;$".contains(next) ||
+               (i > 0 && chars[i-1] == '$') {
+                return None;
+            }
+            let end = (i+1..chars.len()).find(|&j| {
+                chars[j] == '$' && !chars[j-1].is_whitespace() &&
+                !".,$".contains(chars[j-1]) &&
+                chars.get(j+1).is_none_or(|c| c.is_whitespace() ||
+                                              "-.,;:!?')\"".contains(*c))
+            })?;
+            fragment(end + 1)
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,45 @@
+extern crate exorg;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use exorg::export::{Exporter, Options};
+
+/// Empty directory for the files of one test
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("exorg-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn keeps_references_with_noweb_inherited_from_including_file() {
+    let dir = test_dir("detangle-inherited-noweb");
+    let root = dir.join("root.org");
+    let lib  = dir.join("lib.org");
+    let lib_content = "* Code\n\
+                       #+BEGIN_SRC python :tangle out.py\n\
+                       <<part>>\n\
+                       print(x)\n\
+                       #+END_SRC\n\
+                       \n\
+                       #+NAME: part\n\
+                       #+BEGIN_SRC python\n\
+                       x = 1\n\
+                       #+END_SRC\n";
+    fs::write(&root, "#+PROPERTY: header-args :noweb yes :comments link\n\
+                      #+INCLUDE: \"lib.org\"\n").unwrap();
+    fs::write(&lib, lib_content).unwrap();
+
+    let exporter = Exporter::from_file(root.to_str().unwrap()).unwrap();
+    exporter.export(".", &Options::default()).unwrap();
+    let out = dir.join("out.py");
+    assert!(fs::read_to_string(&out).unwrap().contains("x = 1\n"));
+
+    Exporter::detangle(out.to_str().unwrap()).unwrap();
+    assert_eq!(fs::read_to_string(&lib).unwrap(), lib_content);
+
+    fs::remove_dir_all(&dir).unwrap();
+}